@@ -3,9 +3,8 @@ use std::collections::BTreeSet;
 use std::sync::Arc;
 use std::sync::OnceLock;
 
-use paperless_rs::PaperlessClient;
+use jiff::tz::TimeZone;
 use tracing::debug;
-use trustfall::provider::resolve_coercion_using_schema;
 use trustfall::provider::resolve_property_with;
 use trustfall::provider::AsVertex;
 use trustfall::provider::ContextIterator;
@@ -18,19 +17,21 @@ use trustfall::provider::VertexIterator;
 use trustfall::FieldValue;
 use trustfall::Schema;
 
+use super::paperless::Paperless;
+use super::store::RecordStore;
 use super::vertex::Vertex;
+use crate::config::PaperlessConfig;
 use crate::parsing::DefinitionKind;
-use crate::parsing::Record;
 
 static SCHEMA: OnceLock<Schema> = OnceLock::new();
 
 #[non_exhaustive]
 pub struct Adapter {
     schema: Arc<Schema>,
-    records: Vec<Record>,
+    records: Arc<RecordStore>,
     definitions: Arc<BTreeMap<String, BTreeMap<String, DefinitionKind>>>,
-    paperless_client: Option<PaperlessClient>,
-    runtime_handle: tokio::runtime::Handle,
+    paperless: Option<Arc<Paperless>>,
+    timezone: TimeZone,
 }
 
 impl std::fmt::Debug for Adapter {
@@ -42,20 +43,26 @@ impl std::fmt::Debug for Adapter {
 impl Adapter {
     pub fn new(
         schema: Schema,
-        records: Vec<Record>,
+        records: Arc<RecordStore>,
         definitions: BTreeMap<String, BTreeMap<String, DefinitionKind>>,
-        paperless_client: Option<PaperlessClient>,
+        paperless: Option<PaperlessConfig>,
+        timezone: TimeZone,
         runtime: tokio::runtime::Handle,
     ) -> Self {
         Self {
             schema: Arc::new(schema),
             records,
             definitions: Arc::new(definitions),
-            paperless_client,
-            runtime_handle: runtime,
+            paperless: paperless.map(|config| Arc::new(Paperless::new(config, runtime))),
+            timezone,
         }
     }
 
+    /// The timezone datetimes without one are read in
+    pub(crate) fn timezone(&self) -> &TimeZone {
+        &self.timezone
+    }
+
     pub const SCHEMA_TEXT: &'static str = include_str!("./schema.graphql");
 
     pub fn schema() -> &'static Schema {
@@ -69,16 +76,32 @@ impl<'a> trustfall::provider::Adapter<'a> for Adapter {
     fn resolve_starting_vertices(
         &self,
         edge_name: &Arc<str>,
-        _parameters: &EdgeParameters,
+        parameters: &EdgeParameters,
         resolve_info: &ResolveInfo,
     ) -> VertexIterator<'a, Self::Vertex> {
         match edge_name.as_ref() {
-            "Records" => super::entrypoints::records(resolve_info, &self.records),
-            _ => {
-                unreachable!(
-                    "attempted to resolve starting vertices for unexpected edge name: {edge_name}"
-                )
+            "Records" => super::entrypoints::records(
+                resolve_info,
+                parameters,
+                &self.records,
+                None,
+                &self.timezone,
+            ),
+            "InvalidRecords" => {
+                super::entrypoints::invalid_records(parameters, &self.records, &self.timezone)
             }
+            entrypoint => match super::entrypoints(self.definitions.keys()).get(entrypoint) {
+                Some(kind) => super::entrypoints::records(
+                    resolve_info,
+                    parameters,
+                    &self.records,
+                    Some(kind),
+                    &self.timezone,
+                ),
+                None => unreachable!(
+                    "attempted to resolve starting vertices for unexpected edge name: {edge_name}"
+                ),
+            },
         }
     }
 
@@ -110,6 +133,16 @@ impl<'a> trustfall::provider::Adapter<'a> for Adapter {
             "Record" => {
                 super::properties::resolve_record_property(contexts, property_name, resolve_info)
             }
+            "InvalidRecord" => super::properties::resolve_invalid_record_property(
+                contexts,
+                property_name.as_ref(),
+                resolve_info,
+            ),
+            "InvalidField" => super::properties::resolve_invalid_field_property(
+                contexts,
+                property_name.as_ref(),
+                resolve_info,
+            ),
             kind if kind.starts_with("p_") => {
                 super::properties::resolve_record_property(contexts, property_name, resolve_info)
             }
@@ -136,12 +169,29 @@ impl<'a> trustfall::provider::Adapter<'a> for Adapter {
                 parameters,
                 resolve_info,
             ),
+            "InvalidRecord" => super::edges::resolve_invalid_record_edge(
+                contexts,
+                edge_name.as_ref(),
+                parameters,
+                resolve_info,
+            ),
+            "Record" => super::edges::resolve_record_edge(
+                contexts,
+                edge_name,
+                parameters,
+                resolve_info,
+                &self.definitions,
+                &self.records,
+                &self.paperless,
+            ),
             kind if kind.starts_with("p_") => super::edges::resolve_record_edge(
                 contexts,
                 edge_name,
                 parameters,
                 resolve_info,
                 &self.definitions,
+                &self.records,
+                &self.paperless,
             ),
             _ => {
                 unreachable!(
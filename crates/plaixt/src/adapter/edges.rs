@@ -2,6 +2,8 @@ use std::collections::BTreeMap;
 use std::sync::Arc;
 
 use camino::Utf8PathBuf;
+use tracing::debug;
+use tracing::warn;
 use trustfall::provider::resolve_neighbors_with;
 use trustfall::provider::AsVertex;
 use trustfall::provider::ContextIterator;
@@ -9,7 +11,10 @@ use trustfall::provider::ContextOutcomeIterator;
 use trustfall::provider::EdgeParameters;
 use trustfall::provider::ResolveEdgeInfo;
 use trustfall::provider::VertexIterator;
+use trustfall::FieldValue;
 
+use super::paperless::Paperless;
+use super::store::RecordStore;
 use super::Vertex;
 use crate::parsing::DefinitionKind;
 use crate::parsing::Record;
@@ -64,26 +69,111 @@ mod directory {
     }
 }
 
+pub(super) fn resolve_invalid_record_edge<'a, V: AsVertex<Vertex> + 'a>(
+    contexts: ContextIterator<'a, V>,
+    edge_name: &str,
+    _parameters: &EdgeParameters,
+    _resolve_info: &ResolveEdgeInfo,
+) -> ContextOutcomeIterator<'a, V, VertexIterator<'a, Vertex>> {
+    match edge_name {
+        "_fields" => resolve_neighbors_with(contexts, |v| {
+            let rec = v.as_invalid_record().expect("Expected an invalid record");
+
+            Box::new(
+                rec.fields
+                    .iter()
+                    .map(|(name, value)| {
+                        Vertex::InvalidField(Arc::new((name.clone(), value.clone())))
+                    })
+                    .collect::<Vec<_>>()
+                    .into_iter(),
+            )
+        }),
+        _ => unreachable!(
+            "attempted to resolve unexpected edge '{edge_name}' on type 'InvalidRecord'"
+        ),
+    }
+}
+
 pub(super) fn resolve_record_edge<'a, V: AsVertex<Vertex> + 'a>(
     contexts: ContextIterator<'a, V>,
     edge_name: &Arc<str>,
-    _parameters: &EdgeParameters,
+    parameters: &EdgeParameters,
     _resolve_info: &ResolveEdgeInfo,
     definitions: &Arc<BTreeMap<String, BTreeMap<String, DefinitionKind>>>,
+    records: &Arc<RecordStore>,
+    paperless: &Option<Arc<Paperless>>,
 ) -> ContextOutcomeIterator<'a, V, VertexIterator<'a, Vertex>> {
+    if edge_name.as_ref() == "_source" {
+        return resolve_neighbors_with(contexts, move |v| {
+            let rec = v.as_record().expect("Expected a record");
+
+            Box::new(
+                rec.source
+                    .iter()
+                    .map(|source| Vertex::Record(source.clone()))
+                    .collect::<Vec<_>>()
+                    .into_iter(),
+            )
+        });
+    }
+
+    if edge_name.as_ref() == "_overlapping" {
+        let kinds = match parameters.get("kind") {
+            Some(FieldValue::String(kind)) => Some(vec![kind.to_string()]),
+            _ => None,
+        };
+        let records = records.clone();
+        return resolve_neighbors_with(contexts, move |v| {
+            let rec = v.as_record().expect("Expected a record");
+
+            Box::new(
+                records
+                    .overlapping(rec, kinds.as_deref())
+                    .map(Vertex::Record),
+            )
+        });
+    }
+
     let edge_name = edge_name.clone();
     let definitions = definitions.clone();
+    let paperless = paperless.clone();
     resolve_neighbors_with(contexts, move |v| {
         let rec = v.as_record().expect("Expected a record");
         let def = &definitions[&rec.kind][edge_name.as_ref()];
 
         match def {
             DefinitionKind::Path => Box::new(std::iter::once(path_from_rec(rec, &edge_name))),
-            _ => unreachable!("Only `Path` can appear as edge for now"),
+            DefinitionKind::Paperless => {
+                Box::new(paperless_document(rec, &edge_name, paperless.as_deref()).into_iter())
+            }
+            _ => unreachable!("Only `Path` and `Paperless` can appear as edge for now"),
         }
     })
 }
 
+/// The paperless document a field refers to, if it can be fetched
+fn paperless_document(
+    rec: &Record,
+    edge_name: &str,
+    paperless: Option<&Paperless>,
+) -> Option<Vertex> {
+    let id = rec.fields.get(edge_name)?.as_integer()?;
+    let Some(paperless) = paperless else {
+        debug!(
+            id,
+            "No paperless instance is configured to fetch the document from"
+        );
+        return None;
+    };
+
+    let document = paperless
+        .document(u64::try_from(id).ok()?)
+        .map_err(|error| warn!(?error, "Leaving out a paperless document"))
+        .ok()?;
+    Some(Vertex::PaperlessDocument(Box::new(document)))
+}
+
 fn path_from_rec(rec: &Record, edge_name: &str) -> Vertex {
     let pathb = Utf8PathBuf::from(rec.fields[edge_name].as_string().unwrap());
     if pathb.is_file() {
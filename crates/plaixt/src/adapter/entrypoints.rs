@@ -1,16 +1,178 @@
+use std::ops::Bound;
+use std::sync::Arc;
+
+use jiff::tz::TimeZone;
+use jiff::SignedDuration;
+use jiff::Timestamp;
+use tracing::warn;
+use trustfall::provider::CandidateValue;
+use trustfall::provider::EdgeParameters;
 use trustfall::provider::ResolveInfo;
+use trustfall::provider::VertexInfo;
 use trustfall::provider::VertexIterator;
+use trustfall::FieldValue;
 
+use super::store::RecordStore;
 use super::vertex::Vertex;
-use crate::parsing::Record;
+use crate::parsing::parse_timestamp;
 
+/// The records of `Records`, or of the entrypoint of `kind`
+///
+/// Only records that can match the query are produced: the `kind`, `from` and `until`
+/// parameters as well as filters on `_at`, `_start` and `_kind` and type coercions narrow down
+/// which records are looked up in the store.
 pub(super) fn records<'a>(
-    _resolve_info: &ResolveInfo,
-    records: &'_ [Record],
+    resolve_info: &ResolveInfo,
+    parameters: &EdgeParameters,
+    store: &Arc<RecordStore>,
+    kind: Option<&str>,
+    timezone: &TimeZone,
 ) -> VertexIterator<'a, Vertex> {
-    #[expect(
-        clippy::unnecessary_to_owned,
-        reason = "We have to go through a vec to satisfy the lifetimes"
-    )]
-    Box::new(records.to_vec().into_iter().map(Vertex::Record))
+    let Some((from, until)) = window_parameters(parameters, timezone) else {
+        return Box::new(std::iter::empty());
+    };
+    let mut windows = vec![(
+        from.map_or(Bound::Unbounded, Bound::Included),
+        until.map_or(Bound::Unbounded, Bound::Excluded),
+    )];
+
+    for property in ["_at", "_start"] {
+        match resolve_info.statically_required_property(property) {
+            Some(CandidateValue::Impossible) => return Box::new(std::iter::empty()),
+            Some(candidates) => windows.push(hinted_window(candidates)),
+            None => {}
+        }
+    }
+
+    let mut kinds = kind.map(|kind| vec![kind.to_string()]);
+    if let Some(FieldValue::String(kind)) = parameters.get("kind") {
+        narrow(&mut kinds, vec![kind.to_string()]);
+    }
+    if let Some(kind) = resolve_info
+        .coerced_to_type()
+        .and_then(|ty| ty.strip_prefix("p_"))
+    {
+        narrow(&mut kinds, vec![kind.to_string()]);
+    }
+    match resolve_info.statically_required_property("_kind") {
+        Some(CandidateValue::Single(FieldValue::String(kind))) => {
+            narrow(&mut kinds, vec![kind.to_string()]);
+        }
+        Some(CandidateValue::Multiple(candidates)) => {
+            let candidates = candidates
+                .into_iter()
+                .filter_map(|kind| kind.as_str().map(str::to_string))
+                .collect();
+            narrow(&mut kinds, candidates);
+        }
+        Some(CandidateValue::Impossible) => return Box::new(std::iter::empty()),
+        _ => {}
+    }
+
+    Box::new(store.select(&windows, kinds.as_deref()).map(Vertex::Record))
+}
+
+/// The `from` and `until` parameters, if given
+///
+/// They are checked when the query is parsed. Should one not be a datetime anyway, nothing is
+/// inside of the window and `None` is returned.
+fn window_parameters(
+    parameters: &EdgeParameters,
+    timezone: &TimeZone,
+) -> Option<(Option<Timestamp>, Option<Timestamp>)> {
+    let parameter = |name: &str| {
+        let Some(value) = parameters.get(name).and_then(FieldValue::as_str) else {
+            return Ok(None);
+        };
+        parse_timestamp(value, timezone)
+            .map(|(at, _)| Some(at.timestamp()))
+            .map_err(|error| warn!(%error, "The {name} parameter \"{value}\" is not a datetime"))
+    };
+
+    Some((parameter("from").ok()?, parameter("until").ok()?))
+}
+
+/// The starts a filter on `_at` or `_start` lets through
+///
+/// The filters compare the properties as strings. This only agrees with comparing them as
+/// timestamps for values written like the properties are, in UTC, and then only up to the
+/// second, so the window is widened by a second to each side. Values written any other way,
+/// e.g. with an offset, leave their side of the window open.
+fn hinted_window(candidates: CandidateValue<FieldValue>) -> (Bound<Timestamp>, Bound<Timestamp>) {
+    let parse = |value: &FieldValue| {
+        let value = value.as_str()?;
+        let at = value.parse::<Timestamp>().ok()?;
+        (at.to_string() == value).then_some(at)
+    };
+    let lower = |at: Option<Timestamp>| {
+        at.and_then(|at| at.checked_sub(SignedDuration::from_secs(1)).ok())
+            .map_or(Bound::Unbounded, Bound::Included)
+    };
+    let upper = |at: Option<Timestamp>| {
+        at.and_then(|at| at.checked_add(SignedDuration::from_secs(1)).ok())
+            .map_or(Bound::Unbounded, Bound::Included)
+    };
+    let value = |bound: Bound<&FieldValue>| match bound {
+        Bound::Included(value) | Bound::Excluded(value) => parse(value),
+        Bound::Unbounded => None,
+    };
+
+    match candidates {
+        CandidateValue::Single(at) => (lower(parse(&at)), upper(parse(&at))),
+        CandidateValue::Multiple(candidates) => {
+            match candidates.iter().map(parse).collect::<Option<Vec<_>>>() {
+                Some(starts) => (
+                    lower(starts.iter().min().copied()),
+                    upper(starts.iter().max().copied()),
+                ),
+                None => (Bound::Unbounded, Bound::Unbounded),
+            }
+        }
+        CandidateValue::Range(range) => (
+            lower(value(range.start_bound())),
+            upper(value(range.end_bound())),
+        ),
+        _ => (Bound::Unbounded, Bound::Unbounded),
+    }
+}
+
+/// The invalid records of `InvalidRecords`
+///
+/// There are few of them, so they are filtered by the `kind`, `from` and `until` parameters one
+/// by one.
+pub(super) fn invalid_records<'a>(
+    parameters: &EdgeParameters,
+    store: &Arc<RecordStore>,
+    timezone: &TimeZone,
+) -> VertexIterator<'a, Vertex> {
+    let kind = parameters
+        .get("kind")
+        .and_then(|kind| kind.as_str())
+        .map(str::to_string);
+    let Some((from, until)) = window_parameters(parameters, timezone) else {
+        return Box::new(std::iter::empty());
+    };
+
+    let records = store
+        .invalid()
+        .iter()
+        .filter(move |record| {
+            let start = record.at.as_ref().map(|at| at.timestamp());
+            kind.as_ref()
+                .is_none_or(|kind| record.kind.as_ref() == Some(kind))
+                && from.is_none_or(|from| start.is_some_and(|start| start >= from))
+                && until.is_none_or(|until| start.is_some_and(|start| start < until))
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+
+    Box::new(records.into_iter().map(Vertex::InvalidRecord))
+}
+
+/// Restricts `kinds` to the ones also in `allowed`
+fn narrow(kinds: &mut Option<Vec<String>>, allowed: Vec<String>) {
+    match kinds {
+        Some(kinds) => kinds.retain(|kind| allowed.contains(kind)),
+        None => *kinds = Some(allowed),
+    }
 }
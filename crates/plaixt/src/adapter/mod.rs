@@ -1,13 +1,18 @@
 mod adapter_impl;
 mod edges;
 mod entrypoints;
+mod paperless;
 mod properties;
+mod store;
 mod vertex;
 
 #[cfg(test)]
 mod tests;
 
+use std::collections::BTreeMap;
+
 pub use adapter_impl::Adapter;
+pub use store::RecordStore;
 use tracing::trace;
 use trustfall::Schema;
 pub use vertex::Vertex;
@@ -22,25 +27,160 @@ impl crate::parsing::Definition {
         let name = format!("p_{}", self.name);
 
         let fields = self
-            .fields
+            .order
             .iter()
+            .filter_map(|fname| Some((fname, self.fields.get(fname)?)))
             .map(|(fname, ftype)| {
                 let kind = ftype.trustfall_kind(&format!("{name}{fname}"));
-                format!("{fname}: {kind}")
+                let description = self
+                    .field_descriptions
+                    .get(fname)
+                    .map(|description| doc_string(description, "    "))
+                    .unwrap_or_default();
+                format!("{description}    {fname}: {kind}\n")
             })
-            .chain([String::from("_at: String!"), String::from("_kind: String!")])
-            .collect::<Vec<_>>();
+            .chain(
+                [
+                    "_at: String!",
+                    "_start: String!",
+                    "_end: String",
+                    "_precision: String!",
+                    "_recurring: Boolean!",
+                    "_source: Record",
+                    "_overlapping(kind: String): [Record!]!",
+                    "_local: String!",
+                    "_date: String!",
+                    "_zone: String!",
+                    "_kind: String!",
+                    "_id: String",
+                    "_file: String!",
+                    "_line: Int!",
+                ]
+                .map(|field| format!("    {field}\n")),
+            )
+            .collect::<String>();
 
-        let definition = format!("type {name} implements Record {{ {} }}", fields.join(","));
+        let description = self
+            .description
+            .as_deref()
+            .map(|description| doc_string(description, ""))
+            .unwrap_or_default();
+        let definition = format!("{description}type {name} implements Record {{\n{fields}}}\n");
 
         [CustomVertex { name, definition }].into_iter().collect()
     }
 }
 
+/// The entrypoints of the base schema, which no kind can have as its own
+const BASE_ENTRYPOINTS: [&str; 2] = ["Records", "InvalidRecords"];
+
+/// The entrypoint of every kind of record, by the name it has in the schema
+pub(crate) fn entrypoints<'a>(
+    kinds: impl IntoIterator<Item = &'a String>,
+) -> BTreeMap<String, &'a str> {
+    assign_entrypoints(kinds).0
+}
+
+/// Warnings about kinds that did not get an entrypoint, as its name was already taken
+pub(crate) fn entrypoint_warnings<'a>(
+    kinds: impl IntoIterator<Item = &'a String>,
+) -> Vec<miette::Report> {
+    assign_entrypoints(kinds).1
+}
+
+/// Gives every kind the entrypoint named after it, unless another kind came first or the
+/// name is one of the [`BASE_ENTRYPOINTS`]
+fn assign_entrypoints<'a>(
+    kinds: impl IntoIterator<Item = &'a String>,
+) -> (BTreeMap<String, &'a str>, Vec<miette::Report>) {
+    let mut entrypoints = BTreeMap::<String, &str>::new();
+    let mut warnings = vec![];
+
+    for kind in kinds {
+        let entrypoint = entrypoint_name(kind);
+        let taken_by = match entrypoints.get(&entrypoint) {
+            Some(other) => format!("that of \"{other}\""),
+            None if BASE_ENTRYPOINTS.contains(&entrypoint.as_str()) => {
+                String::from("a built-in one")
+            }
+            None => {
+                entrypoints.insert(entrypoint, kind.as_str());
+                continue;
+            }
+        };
+
+        warnings.push(miette::miette!(
+            severity = miette::Severity::Warning,
+            help =
+                format!("Query them through `Records(kind: \"{kind}\")`, or rename the definition"),
+            "The records of kind \"{kind}\" have no entrypoint, as `{entrypoint}` is {taken_by}"
+        ));
+    }
+
+    (entrypoints, warnings)
+}
+
+/// Names the entrypoint of a kind in upper camel case, e.g. `FileTest` for `file_test`
+fn entrypoint_name(kind: &str) -> String {
+    kind.split(['_', '-'])
+        .flat_map(|part| {
+            let mut chars = part.chars();
+            chars
+                .next()
+                .map(|first| first.to_uppercase().chain(chars))
+                .into_iter()
+                .flatten()
+        })
+        .collect()
+}
+
+/// Writes `text` as a GraphQL block string, indented to go before a type or field
+fn doc_string(text: &str, indent: &str) -> String {
+    let mut doc = format!("{indent}\"\"\"\n");
+    for line in text.replace("\"\"\"", "\\\"\"\"").lines() {
+        doc.push_str(&format!("{indent}{line}\n"));
+    }
+    doc.push_str(&format!("{indent}\"\"\"\n"));
+    doc
+}
+
 pub(crate) fn to_schema(
     definitions: &std::collections::BTreeMap<String, Vec<crate::parsing::Definition>>,
-) -> trustfall::Schema {
-    let base_text = Adapter::SCHEMA_TEXT;
+) -> miette::Result<trustfall::Schema> {
+    let input = schema_text(definitions);
+    trace!(%input, "Using schema");
+    Schema::parse(input).map_err(|error| {
+        miette::miette!(
+            help = "Definitions are named after their file, which has to be a valid GraphQL name, e.g. `file_test`",
+            "The definitions do not make a valid schema: {error}"
+        )
+    })
+}
+
+/// The schema in GraphQL SDL, with one vertex type per definition
+pub(crate) fn schema_text(
+    definitions: &std::collections::BTreeMap<String, Vec<crate::parsing::Definition>>,
+) -> String {
+    // Every definition gets its own entrypoint next to `Records`
+    let mut entrypoints = String::new();
+    for (entrypoint, kind) in self::entrypoints(definitions.keys()) {
+        let description =
+            format!("All records of kind `{kind}`, ordered by `_start` and then `_end`");
+        entrypoints.push_str(&doc_string(&description, "    "));
+        entrypoints.push_str(&format!(
+            "    {entrypoint}(from: String, until: String): [p_{kind}!]!\n"
+        ));
+    }
+
+    let mut base_text = Adapter::SCHEMA_TEXT.to_string();
+    let root = base_text
+        .find("type RootSchemaQuery {")
+        .expect("the schema has a root query type");
+    let end = root
+        + base_text[root..]
+            .find("\n}\n")
+            .expect("the root query type is closed");
+    base_text.insert_str(end + 1, &entrypoints);
 
     let generated = definitions
         .values()
@@ -49,7 +189,5 @@ pub(crate) fn to_schema(
         .collect::<Vec<_>>()
         .join("\n");
 
-    let input = format!("{base_text}{generated}");
-    trace!(%input, "Using schema");
-    Schema::parse(input).unwrap()
+    format!("{base_text}\n{generated}")
 }
@@ -0,0 +1,52 @@
+use std::sync::OnceLock;
+
+use miette::Context;
+use paperless_rs::endpoint::documents::Document;
+use paperless_rs::PaperlessClient;
+
+use crate::config::PaperlessConfig;
+
+/// The configured paperless instance, which documents of `paperless` fields are fetched from
+///
+/// Connecting happens on first use, so that only queries reaching into paperless depend on it
+/// being reachable.
+pub(crate) struct Paperless {
+    config: PaperlessConfig,
+    client: OnceLock<PaperlessClient>,
+    runtime_handle: tokio::runtime::Handle,
+}
+
+impl Paperless {
+    pub(crate) fn new(config: PaperlessConfig, runtime: tokio::runtime::Handle) -> Paperless {
+        Paperless {
+            config,
+            client: OnceLock::new(),
+            runtime_handle: runtime,
+        }
+    }
+
+    /// The document with `id`
+    pub(crate) fn document(&self, id: u64) -> miette::Result<Document> {
+        let client = self.client()?;
+
+        // Queries run synchronously on a worker of the runtime
+        tokio::task::block_in_place(|| self.runtime_handle.block_on(client.fetch_document(id)))
+            .map_err(|e| miette::miette!("{e}"))
+            .wrap_err_with(|| {
+                format!(
+                    "Could not fetch document {id} from paperless at \"{}\"",
+                    self.config.url
+                )
+            })
+    }
+
+    fn client(&self) -> miette::Result<&PaperlessClient> {
+        if let Some(client) = self.client.get() {
+            return Ok(client);
+        }
+
+        let client =
+            tokio::task::block_in_place(|| self.runtime_handle.block_on(self.config.client()))?;
+        Ok(self.client.get_or_init(|| client))
+    }
+}
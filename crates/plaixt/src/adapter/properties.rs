@@ -156,9 +156,63 @@ pub(super) fn resolve_record_property<'a, V: AsVertex<Vertex> + 'a>(
     match property_name.as_ref() {
         "_at" => resolve_property_with(
             contexts,
-            field_property!(as_record, at, { at.to_string().into() }),
+            field_property!(as_record, at, { at.timestamp().to_string().into() }),
+        ),
+        "_start" => resolve_property_with(
+            contexts,
+            field_property!(as_record, at, { at.timestamp().to_string().into() }),
+        ),
+        "_end" => resolve_property_with(contexts, move |v: &Vertex| {
+            let rec = v
+                .as_record()
+                .expect("Called record property without it being a record");
+
+            rec.end()
+                .map(|end| end.timestamp().to_string().into())
+                .unwrap_or(FieldValue::Null)
+        }),
+        "_recurring" => resolve_property_with(
+            contexts,
+            field_property!(as_record, source, { source.is_some().into() }),
+        ),
+        "_precision" => resolve_property_with(
+            contexts,
+            field_property!(as_record, precision, { precision.as_str().into() }),
+        ),
+        "_local" => resolve_property_with(
+            contexts,
+            field_property!(as_record, at, { at.datetime().to_string().into() }),
+        ),
+        "_date" => resolve_property_with(
+            contexts,
+            field_property!(as_record, at, { at.date().to_string().into() }),
+        ),
+        "_zone" => resolve_property_with(
+            contexts,
+            field_property!(as_record, at, {
+                match at.time_zone().iana_name() {
+                    Some(name) => name.into(),
+                    None => at.offset().to_string().into(),
+                }
+            }),
         ),
         "_kind" => resolve_property_with(contexts, field_property!(as_record, kind)),
+        "_id" => resolve_property_with(
+            contexts,
+            field_property!(as_record, id, {
+                id.as_deref().map_or(FieldValue::Null, FieldValue::from)
+            }),
+        ),
+        "_file" => resolve_property_with(
+            contexts,
+            field_property!(as_record, location, { location.file.to_string().into() }),
+        ),
+        "_line" => resolve_property_with(
+            contexts,
+            field_property!(as_record, location, {
+                FieldValue::Int64(location.line as i64)
+            }),
+        ),
         _ => resolve_property_with(contexts, move |v: &Vertex| {
             let rec = v
                 .as_record()
@@ -169,6 +223,75 @@ pub(super) fn resolve_record_property<'a, V: AsVertex<Vertex> + 'a>(
     }
 }
 
+pub(super) fn resolve_invalid_record_property<'a, V: AsVertex<Vertex> + 'a>(
+    contexts: ContextIterator<'a, V>,
+    property_name: &str,
+    _resolve_info: &ResolveInfo,
+) -> ContextOutcomeIterator<'a, V, FieldValue> {
+    match property_name {
+        "_kind" => resolve_property_with(
+            contexts,
+            field_property!(as_invalid_record, kind, {
+                kind.as_deref().map_or(FieldValue::Null, FieldValue::from)
+            }),
+        ),
+        "_at" => resolve_property_with(
+            contexts,
+            field_property!(as_invalid_record, at, {
+                at.as_ref()
+                    .map_or(FieldValue::Null, |at| at.timestamp().to_string().into())
+            }),
+        ),
+        "_errors" => resolve_property_with(
+            contexts,
+            field_property!(as_invalid_record, errors, { errors.clone().into() }),
+        ),
+        "_file" => resolve_property_with(
+            contexts,
+            field_property!(as_invalid_record, location, {
+                location.file.to_string().into()
+            }),
+        ),
+        "_line" => resolve_property_with(
+            contexts,
+            field_property!(as_invalid_record, location, {
+                FieldValue::Int64(location.line as i64)
+            }),
+        ),
+        _ => unreachable!(
+            "attempted to read unexpected property '{property_name}' on type 'InvalidRecord'"
+        ),
+    }
+}
+
+pub(super) fn resolve_invalid_field_property<'a, V: AsVertex<Vertex> + 'a>(
+    contexts: ContextIterator<'a, V>,
+    property_name: &str,
+    _resolve_info: &ResolveInfo,
+) -> ContextOutcomeIterator<'a, V, FieldValue> {
+    match property_name {
+        "name" => resolve_property_with(contexts, |v: &Vertex| {
+            let (name, _) = &**v
+                .as_invalid_field()
+                .expect("vertex was not an InvalidField");
+            name.as_str().into()
+        }),
+        "value" => resolve_property_with(contexts, |v: &Vertex| {
+            let (_, value) = &**v
+                .as_invalid_field()
+                .expect("vertex was not an InvalidField");
+            match value {
+                KdlValue::String(s) => s.as_str().into(),
+                KdlValue::Null => FieldValue::Null,
+                value => value.to_string().into(),
+            }
+        }),
+        _ => unreachable!(
+            "attempted to read unexpected property '{property_name}' on type 'InvalidField'"
+        ),
+    }
+}
+
 fn kdl_to_trustfall_value(val: KdlValue) -> FieldValue {
     match val {
         KdlValue::Bool(b) => FieldValue::Boolean(b),
@@ -0,0 +1,134 @@
+use std::collections::BTreeMap;
+use std::ops::Bound;
+use std::ops::Range;
+use std::sync::Arc;
+
+use jiff::Timestamp;
+
+use crate::parsing::InvalidRecord;
+use crate::parsing::Record;
+
+/// All loaded records, sorted by when they start and indexed by kind
+///
+/// The store is built once after loading and never changes, so it is shared behind an [`Arc`]
+/// by the adapter and everything else looking at the records. Vertices hold an [`Arc`] of their
+/// record instead of a copy of it.
+///
+/// Records that were loaded leniently despite not being valid are kept apart, in the order of
+/// their files.
+#[derive(Debug, Default)]
+pub struct RecordStore {
+    records: Vec<Arc<Record>>,
+    starts: Vec<Timestamp>,
+    kinds: BTreeMap<String, Vec<usize>>,
+    invalid: Vec<Arc<InvalidRecord>>,
+}
+
+impl RecordStore {
+    pub fn new(mut records: Vec<Record>, invalid: Vec<InvalidRecord>) -> Arc<RecordStore> {
+        // Stable, so records starting at the same time stay ordered by their end
+        records.sort_by_key(|record| record.at.timestamp());
+
+        let starts = records.iter().map(|record| record.at.timestamp()).collect();
+
+        let mut kinds = BTreeMap::<String, Vec<usize>>::new();
+        for (idx, record) in records.iter().enumerate() {
+            kinds.entry(record.kind.clone()).or_default().push(idx);
+        }
+
+        Arc::new(RecordStore {
+            records: records.into_iter().map(Arc::new).collect(),
+            starts,
+            kinds,
+            invalid: invalid.into_iter().map(Arc::new).collect(),
+        })
+    }
+
+    /// All records, ordered by when they start
+    pub fn records(&self) -> &[Arc<Record>] {
+        &self.records
+    }
+
+    /// The records that are not valid, ordered by where they are written
+    pub fn invalid(&self) -> &[Arc<InvalidRecord>] {
+        &self.invalid
+    }
+
+    /// The records starting within all of `windows`, of one of `kinds` if given, in order
+    ///
+    /// Only the positions of the records are looked up here, the records themselves are handed
+    /// out as the iterator is advanced.
+    pub fn select(
+        self: &Arc<Self>,
+        windows: &[(Bound<Timestamp>, Bound<Timestamp>)],
+        kinds: Option<&[String]>,
+    ) -> impl Iterator<Item = Arc<Record>> + 'static {
+        let range = windows
+            .iter()
+            .map(|window| self.positions(window))
+            .fold(0..self.records.len(), |range, window| {
+                range.start.max(window.start)..range.end.min(window.end)
+            });
+
+        let positions = match kinds {
+            _ if range.is_empty() => vec![],
+            None => range.collect(),
+            Some(kinds) => {
+                let mut positions = kinds
+                    .iter()
+                    .filter_map(|kind| self.kinds.get(kind))
+                    .flat_map(|positions| {
+                        let from = positions.partition_point(|pos| *pos < range.start);
+                        let until = positions.partition_point(|pos| *pos < range.end);
+                        &positions[from..until]
+                    })
+                    .copied()
+                    .collect::<Vec<_>>();
+                positions.sort_unstable();
+                positions.dedup();
+                positions
+            }
+        };
+
+        let store = self.clone();
+        positions
+            .into_iter()
+            .map(move |pos| store.records[pos].clone())
+    }
+
+    /// The other records sharing at least one instant with `record`, of one of `kinds` if
+    /// given, in order
+    ///
+    /// Records may last arbitrarily long, so every record starting before `record` ends is
+    /// looked at.
+    pub fn overlapping(
+        self: &Arc<Self>,
+        record: &Arc<Record>,
+        kinds: Option<&[String]>,
+    ) -> impl Iterator<Item = Arc<Record>> + 'static {
+        let until = match record.end() {
+            Some(end) => Bound::Excluded(end.timestamp()),
+            None => Bound::Included(record.at.timestamp()),
+        };
+
+        let record = record.clone();
+        self.select(&[(Bound::Unbounded, until)], kinds)
+            .filter(move |other| !Arc::ptr_eq(other, &record) && other.overlaps(&record))
+    }
+
+    /// The positions of the records starting within a window
+    fn positions(&self, (from, until): &(Bound<Timestamp>, Bound<Timestamp>)) -> Range<usize> {
+        let from = match from {
+            Bound::Included(from) => self.starts.partition_point(|start| start < from),
+            Bound::Excluded(from) => self.starts.partition_point(|start| start <= from),
+            Bound::Unbounded => 0,
+        };
+        let until = match until {
+            Bound::Included(until) => self.starts.partition_point(|start| start <= until),
+            Bound::Excluded(until) => self.starts.partition_point(|start| start < until),
+            Bound::Unbounded => self.starts.len(),
+        };
+
+        from..until.max(from)
+    }
+}
@@ -1,16 +1,349 @@
+use std::collections::BTreeMap;
+use std::ops::Bound;
+use std::sync::Arc;
+
+use jiff::tz::TimeZone;
 use trustfall::provider::check_adapter_invariants;
+use trustfall::FieldValue;
 
 use super::Adapter;
+use super::RecordStore;
+use crate::parsing::collect_records;
+use crate::parsing::parse_definition;
+use crate::parsing::parse_record_leniently;
+use crate::parsing::LoadOptions;
+use crate::parsing::Location;
+use crate::parsing::Precision;
+use crate::parsing::Record;
+
+/// A record of `kind` without any fields, starting at `at` and ending at `until`
+fn record(kind: &str, at: &str, precision: Precision, until: Option<&str>) -> Record {
+    Record {
+        kind: kind.to_string(),
+        id: None,
+        at: at.parse().unwrap(),
+        precision,
+        until: until.map(|until| until.parse().unwrap()),
+        recurrence: None,
+        source: None,
+        location: Location::new("records.kdl".into(), "", (0, 0).into()),
+        fields: Default::default(),
+    }
+}
 
 #[tokio::test]
 async fn adapter_satisfies_trustfall_invariants() {
     let schema = Adapter::schema();
     let adapter = Adapter::new(
         schema.clone(),
-        vec![],
+        RecordStore::new(vec![], vec![]),
         [].into(),
         None,
+        TimeZone::UTC,
         tokio::runtime::Handle::current(),
     );
     check_adapter_invariants(schema, adapter);
 }
+
+#[test]
+fn every_kind_has_an_entrypoint() {
+    let kinds = [
+        "changelog",
+        "file-test",
+        "file_test",
+        "invalid_records",
+        "records",
+    ]
+    .map(String::from);
+
+    assert_eq!(
+        super::entrypoints(&kinds).into_iter().collect::<Vec<_>>(),
+        [("Changelog", "changelog"), ("FileTest", "file-test")].map(|(e, k)| (e.to_string(), k))
+    );
+
+    let warnings = super::entrypoint_warnings(&kinds)
+        .iter()
+        .map(|warning| warning.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(warnings.len(), 3);
+    assert!(warnings[0].contains("`FileTest` is that of \"file-test\""));
+    assert!(warnings[1].contains("`InvalidRecords` is a built-in one"));
+    assert!(warnings[2].contains("`Records` is a built-in one"));
+}
+
+#[test]
+fn kinds_named_like_built_in_entrypoints_keep_the_schema_valid() {
+    let definitions = |kinds: &[&str]| {
+        kinds
+            .iter()
+            .map(|kind| {
+                let definition = parse_definition(
+                    "define since=\"2024-01-01\" {\n\tfields {\n\t}\n}\n",
+                    kind.to_string(),
+                    &TimeZone::UTC,
+                )
+                .unwrap();
+                (kind.to_string(), definition)
+            })
+            .collect::<BTreeMap<_, _>>()
+    };
+
+    assert!(super::to_schema(&definitions(&["file_test", "invalid_records", "records"])).is_ok());
+    assert!(super::to_schema(&definitions(&["file-test"])).is_err());
+}
+
+#[test]
+fn store_selects_by_start_and_kind() {
+    let record = |kind: &str, at: &str| record(kind, at, Precision::Day, None);
+
+    let store = RecordStore::new(
+        vec![
+            record("changelog", "2025-03-01[UTC]"),
+            record("note", "2025-01-01[UTC]"),
+            record("changelog", "2025-02-01[UTC]"),
+            record("changelog", "2025-01-15[UTC]"),
+        ],
+        vec![],
+    );
+    fn starts(records: impl Iterator<Item = Arc<Record>>) -> Vec<(String, String)> {
+        records
+            .map(|record| (record.kind.clone(), record.at.date().to_string()))
+            .collect()
+    }
+
+    let since_january = (
+        Bound::Included("2025-01-01T00:00:00Z".parse().unwrap()),
+        Bound::Unbounded,
+    );
+    let before_march = (
+        Bound::Unbounded,
+        Bound::Excluded("2025-03-01T00:00:00Z".parse().unwrap()),
+    );
+
+    assert_eq!(
+        starts(store.select(&[since_january, before_march], None)),
+        [
+            ("note", "2025-01-01"),
+            ("changelog", "2025-01-15"),
+            ("changelog", "2025-02-01")
+        ]
+        .map(|(kind, date)| (kind.to_string(), date.to_string()))
+    );
+    assert_eq!(
+        starts(store.select(&[before_march], Some(&["changelog".to_string()]))),
+        [("changelog", "2025-01-15"), ("changelog", "2025-02-01")]
+            .map(|(kind, date)| (kind.to_string(), date.to_string()))
+    );
+}
+
+#[test]
+fn overlapping_records_share_an_instant() {
+    let store = RecordStore::new(
+        vec![
+            record("rental", "2024-12-01[UTC]", Precision::Month, None),
+            record(
+                "trip",
+                "2025-01-10[UTC]",
+                Precision::Day,
+                Some("2025-01-20[UTC]"),
+            ),
+            record("note", "2025-01-05[UTC]", Precision::Day, None),
+            record("note", "2025-01-15[UTC]", Precision::Day, None),
+            record("note", "2025-01-20[UTC]", Precision::Day, None),
+            record("call", "2025-01-12T09:00[UTC]", Precision::Time, None),
+            record("call", "2025-01-12T09:00[UTC]", Precision::Time, None),
+        ],
+        vec![],
+    );
+    let overlapping = |kind: &str, kinds: Option<&[String]>| {
+        let rec = store.records().iter().find(|rec| rec.kind == kind).unwrap();
+        store
+            .overlapping(rec, kinds)
+            .map(|rec| format!("{} {}", rec.kind, rec.at.date()))
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        overlapping("trip", None),
+        ["call 2025-01-12", "call 2025-01-12", "note 2025-01-15"]
+    );
+    assert_eq!(
+        overlapping("trip", Some(&["note".to_string()])),
+        ["note 2025-01-15"]
+    );
+    assert_eq!(
+        overlapping("call", None),
+        ["trip 2025-01-10", "call 2025-01-12"]
+    );
+    assert!(overlapping("rental", None).is_empty());
+}
+
+#[tokio::test]
+async fn invalid_records_are_queried_by_their_start() {
+    let definitions = [(
+        String::from("purchase"),
+        parse_definition(
+            "define since=\"2019-01-01\" {\n\tfields {\n\t\tname is=string\n\t}\n}\n",
+            String::from("purchase"),
+            &TimeZone::UTC,
+        )
+        .unwrap(),
+    )]
+    .into();
+    let files = [
+        (
+            "2019.plrecs",
+            "purchase \"2019-03-01\" id=\"bread\" {\n\tname \"Bread\"\n}\npurchase \"2019-03-02\" {\n\tstore \"Corner\"\n}\n",
+        ),
+        (
+            "2020.plrecs",
+            "purchase \"2019-12-31\" id=\"bread\" {\n\tname \"Bread\"\n}\npurchase \"2020-01-02\" {\n\tname 12\n}\n",
+        ),
+    ]
+    .map(|(file, bytes)| parse_record_leniently(bytes, file.into(), &definitions, &TimeZone::UTC));
+    let options = LoadOptions {
+        window: Default::default(),
+        cache: false,
+        lenient: true,
+    };
+    let (records, invalid) = collect_records(files, &options).unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(invalid.len(), 3);
+
+    let adapter = Arc::new(Adapter::new(
+        Adapter::schema().clone(),
+        RecordStore::new(records, invalid),
+        [].into(),
+        None,
+        TimeZone::UTC,
+        tokio::runtime::Handle::current(),
+    ));
+    let rows = trustfall::execute_query(
+        Adapter::schema(),
+        adapter,
+        r#"{ InvalidRecords(from: "2019", until: "2020") { _at @output _errors @output } }"#,
+        BTreeMap::<Arc<str>, FieldValue>::new(),
+    )
+    .unwrap()
+    .map(|row| (row["_at"].clone(), row["_errors"].clone()))
+    .collect::<Vec<_>>();
+
+    assert_eq!(
+        rows,
+        [
+            (
+                FieldValue::from("2019-03-02T00:00:00Z"),
+                FieldValue::List(
+                    [FieldValue::from(
+                        "This field is not part of the definition. Fields of \"purchase\" are: name"
+                    )]
+                    .into()
+                )
+            ),
+            (
+                FieldValue::from("2019-12-31T00:00:00Z"),
+                FieldValue::List(
+                    [FieldValue::from(
+                        "The id \"bread\" is not unique. It is already used by the record at 2019.plrecs:1"
+                    )]
+                    .into()
+                )
+            ),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn filters_on_the_start_compare_as_strings() {
+    let records = vec![
+        record("note", "2025-01-31T23:30[UTC]", Precision::Time, None),
+        record("note", "2025-02-05[UTC]", Precision::Day, None),
+    ];
+    let adapter = Arc::new(Adapter::new(
+        Adapter::schema().clone(),
+        RecordStore::new(records, vec![]),
+        [].into(),
+        None,
+        TimeZone::UTC,
+        tokio::runtime::Handle::current(),
+    ));
+
+    let starts = |before: &str| {
+        trustfall::execute_query(
+            Adapter::schema(),
+            adapter.clone(),
+            r#"{ Records { _at @output @filter(op: "<", value: ["$before"]) } }"#,
+            [(Arc::from("before"), FieldValue::from(before))]
+                .into_iter()
+                .collect::<BTreeMap<_, _>>(),
+        )
+        .unwrap()
+        .map(|row| row["_at"].clone())
+        .collect::<Vec<_>>()
+    };
+
+    // Earlier than the record as a timestamp, but later as a string
+    assert_eq!(
+        starts("2025-02-01T00:00:00+01:00"),
+        [FieldValue::from("2025-01-31T23:30:00Z")]
+    );
+    assert_eq!(
+        starts("2025-02-01T00:00:00Z"),
+        [FieldValue::from("2025-01-31T23:30:00Z")]
+    );
+}
+
+#[tokio::test]
+async fn paperless_fields_lead_to_their_document() {
+    let definitions = [(
+        String::from("purchase"),
+        parse_definition(
+            "define since=\"2024-01-01\" {\n\tfields {\n\t\treceipt is=\"paperless\"\n\t}\n}\n",
+            String::from("purchase"),
+            &TimeZone::UTC,
+        )
+        .unwrap(),
+    )]
+    .into();
+    assert!(crate::parsing::parse_record(
+        "purchase \"2025-01-01\" {\n\treceipt \"scan.pdf\"\n}\n",
+        "records.plrecs".into(),
+        &definitions,
+        &TimeZone::UTC,
+    )
+    .is_err());
+    let records = crate::parsing::parse_record(
+        "purchase \"2025-01-01\" {\n\treceipt 12\n}\n",
+        "records.plrecs".into(),
+        &definitions,
+        &TimeZone::UTC,
+    )
+    .unwrap();
+
+    let schema = super::to_schema(&definitions).unwrap();
+    let adapter = Arc::new(Adapter::new(
+        schema.clone(),
+        RecordStore::new(records, vec![]),
+        [(
+            String::from("purchase"),
+            definitions["purchase"][0].fields.clone(),
+        )]
+        .into(),
+        None,
+        TimeZone::UTC,
+        tokio::runtime::Handle::current(),
+    ));
+
+    // Without a paperless instance, there is no document to follow the field to
+    let rows = trustfall::execute_query(
+        &schema,
+        adapter,
+        "{ Purchase { _at @output receipt @optional { title @output } } }",
+        BTreeMap::<Arc<str>, FieldValue>::new(),
+    )
+    .unwrap()
+    .collect::<Vec<_>>();
+
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0]["title"], FieldValue::Null);
+}
@@ -1,6 +1,10 @@
+use std::sync::Arc;
+
 use camino::Utf8PathBuf;
+use kdl::KdlValue;
 use paperless_rs::endpoint::documents::Document as PaperlessDocument;
 
+use crate::parsing::InvalidRecord;
 use crate::parsing::Record;
 
 #[non_exhaustive]
@@ -10,6 +14,9 @@ pub enum Vertex {
     File(Utf8PathBuf),
     Directory(Utf8PathBuf),
 
-    PaperlessDocument(PaperlessDocument),
-    Record(Record),
+    PaperlessDocument(Box<PaperlessDocument>),
+    Record(Arc<Record>),
+    InvalidRecord(Arc<InvalidRecord>),
+    /// A field of an invalid record, by name
+    InvalidField(Arc<(String, KdlValue)>),
 }
@@ -0,0 +1,367 @@
+use std::collections::BTreeMap;
+use std::io::IsTerminal;
+
+use camino::Utf8Path;
+use camino::Utf8PathBuf;
+use jiff::tz::TimeZone;
+use jiff::Zoned;
+use kdl::KdlEntry;
+use kdl::KdlNode;
+use kdl::KdlValue;
+use miette::IntoDiagnostic;
+use miette::NamedSource;
+use tokio::io::AsyncWriteExt;
+
+use crate::config::Route;
+use crate::parsing::definition_at;
+use crate::parsing::format_timestamp;
+use crate::parsing::parse_record;
+use crate::parsing::parse_timestamp;
+use crate::parsing::Definition;
+use crate::parsing::DefinitionKind;
+use crate::parsing::Precision;
+
+/// A record to be appended to the repository
+#[derive(Debug)]
+pub struct NewRecord {
+    pub(crate) kind: String,
+    pub(crate) id: Option<String>,
+    pub(crate) start: Zoned,
+    /// The datetime as it will be written
+    pub(crate) at: String,
+    pub(crate) fields: BTreeMap<String, KdlValue>,
+}
+
+impl NewRecord {
+    /// Collects all fields of the definition live at `at`, prompting for missing ones if allowed
+    pub(crate) fn build(
+        definitions: &BTreeMap<String, Vec<Definition>>,
+        kind: &str,
+        id: Option<String>,
+        at: Option<&str>,
+        given: Vec<(String, String)>,
+        prompt: bool,
+        timezone: &TimeZone,
+    ) -> miette::Result<NewRecord> {
+        let Some(defs) = definitions.get(kind) else {
+            miette::bail!(
+                help = format!(
+                    "Known kinds are: {}",
+                    definitions.keys().cloned().collect::<Vec<_>>().join(", ")
+                ),
+                "Unknown record kind \"{kind}\""
+            );
+        };
+
+        let (at, written_at) = match at {
+            Some(at) => (parse_timestamp(at, timezone)?.0, at.to_string()),
+            None => {
+                let now = Zoned::now().with_time_zone(timezone.clone());
+                let now = now.with().subsec_nanosecond(0).build().unwrap_or(now);
+                let written = format_timestamp(&now, Precision::Time, timezone);
+                (now, written)
+            }
+        };
+
+        let definition = definition_at(defs, at.timestamp());
+        let prompt = prompt && std::io::stdin().is_terminal();
+
+        let mut given: BTreeMap<String, String> = given.into_iter().collect();
+        let mut fields = BTreeMap::new();
+
+        if let Some(description) = definition.description.as_ref().filter(|_| prompt) {
+            eprintln!("{kind}: {description}");
+        }
+
+        for name in &definition.order {
+            let field_kind = &definition.fields[name];
+            let default = definition.defaults.get(name);
+            let description = definition.field_descriptions.get(name);
+
+            let value = match given.remove(name) {
+                Some(value) => field_kind.parse_value(value),
+                None if prompt => prompt_field(name, description, field_kind, default)?,
+                None => match default {
+                    Some(default) => default.clone(),
+                    None => miette::bail!(
+                        help = format!("Pass it as `{name}=<value>`"),
+                        "The field \"{name}\" is missing"
+                    ),
+                },
+            };
+
+            field_kind.validate(&value).map_err(|e| {
+                miette::miette!(help = e, "The field \"{name}\" has the wrong kind")
+            })?;
+
+            fields.insert(name.clone(), value);
+        }
+
+        if let Some(unknown) = given.keys().next() {
+            miette::bail!(
+                help = format!(
+                    "Fields of \"{kind}\" are: {}",
+                    definition
+                        .fields
+                        .keys()
+                        .cloned()
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+                "Unknown field \"{unknown}\""
+            );
+        }
+
+        Ok(NewRecord {
+            kind: kind.to_string(),
+            id,
+            start: at,
+            at: written_at,
+            fields,
+        })
+    }
+
+    /// Writes this record as `plaixt fmt` would
+    pub(crate) fn to_kdl(&self, definitions: &BTreeMap<String, Vec<Definition>>) -> String {
+        let mut node = KdlNode::new(self.kind.as_str());
+        node.push(KdlEntry::new(self.at.as_str()));
+        if let Some(id) = &self.id {
+            node.push(KdlEntry::new_prop("id", id.as_str()));
+        }
+
+        let children = node.ensure_children();
+        for (name, value) in &self.fields {
+            let mut field = KdlNode::new(name.as_str());
+            field.push(KdlEntry::new(value.clone()));
+            children.nodes_mut().push(field);
+        }
+
+        let definition = definition_at(&definitions[&self.kind], self.start.timestamp());
+        crate::fmt::format_record(&mut node, definition);
+        node.to_string()
+    }
+
+    /// Appends this record to `file`, after checking that it parses like any other record
+    pub(crate) async fn append(
+        &self,
+        file: &Utf8Path,
+        definitions: &BTreeMap<String, Vec<Definition>>,
+        timezone: &TimeZone,
+    ) -> miette::Result<()> {
+        let text = self.to_kdl(definitions);
+        parse_record(&text, file, definitions, timezone)
+            .map_err(|e| e.with_source_code(NamedSource::new("new record", text.clone())))?;
+
+        if let Some(parent) = file.parent() {
+            tokio::fs::create_dir_all(parent).await.into_diagnostic()?;
+        }
+
+        let _lock = crate::edit::lock(file).await?;
+
+        let existing = match tokio::fs::read_to_string(file).await {
+            Ok(existing) => existing,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e).into_diagnostic(),
+        };
+
+        let separator = match existing.as_str() {
+            "" => "",
+            existing if existing.ends_with("\n\n") => "",
+            existing if existing.ends_with('\n') => "\n",
+            _ => "\n\n",
+        };
+
+        let mut handle = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(file)
+            .await
+            .into_diagnostic()?;
+
+        handle
+            .write_all(format!("{separator}{}\n", text.trim_end()).as_bytes())
+            .await
+            .into_diagnostic()?;
+
+        // Writes finish in the background otherwise, possibly after the lock is released
+        handle.flush().await.into_diagnostic()
+    }
+
+    /// Picks the file of the first route matching this record, relative to `root_folder`
+    pub(crate) fn route(&self, routes: &[Route], root_folder: &Utf8Path) -> Utf8PathBuf {
+        let kind = self.kind.as_str();
+        let at = &self.start;
+        let route = routes
+            .iter()
+            .find(|route| route.kind.as_deref().is_none_or(|k| k == kind))
+            .cloned()
+            .unwrap_or_else(Route::default_route);
+
+        let path = route
+            .template
+            .replace("{kind}", kind)
+            .replace("{year}", &format!("{:04}", at.year()))
+            .replace("{month}", &format!("{:02}", at.month()))
+            .replace("{day}", &format!("{:02}", at.day()));
+
+        root_folder.join(path)
+    }
+}
+
+fn prompt_field(
+    name: &str,
+    description: Option<&String>,
+    kind: &DefinitionKind,
+    default: Option<&KdlValue>,
+) -> miette::Result<KdlValue> {
+    let default = default.and_then(KdlValue::as_string);
+    let name = match description {
+        Some(description) => format!("{name} ({})", description.replace('\n', " ")),
+        None => name.to_string(),
+    };
+
+    let value = match kind {
+        DefinitionKind::OneOf(options) => {
+            let selected = dialoguer::Select::new()
+                .with_prompt(&name)
+                .items(options)
+                .default(
+                    default
+                        .and_then(|default| options.iter().position(|o| o == default))
+                        .unwrap_or(0),
+                )
+                .interact()
+                .into_diagnostic()?;
+            options[selected].clone()
+        }
+        DefinitionKind::String | DefinitionKind::Path | DefinitionKind::Paperless => {
+            let mut input = dialoguer::Input::<String>::new().with_prompt(&name);
+            if let Some(default) = default {
+                input = input.default(default.to_string());
+            }
+            input.interact_text().into_diagnostic()?
+        }
+    };
+
+    Ok(kind.parse_value(value))
+}
+
+/// Parses a `field=value` command line argument
+pub(crate) fn parse_assignment(arg: &str) -> Result<(String, String), String> {
+    arg.split_once('=')
+        .map(|(name, value)| (name.trim().to_string(), value.to_string()))
+        .ok_or_else(|| format!("Expected `field=value`, got \"{arg}\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::time::Duration;
+
+    use jiff::tz::TimeZone;
+
+    use super::NewRecord;
+    use crate::config::Route;
+    use crate::parsing::parse_definition;
+    use crate::parsing::parse_record;
+    use crate::parsing::Definition;
+
+    fn definitions() -> BTreeMap<String, Vec<Definition>> {
+        ["purchase", "note"]
+            .map(|kind| {
+                let definition = parse_definition(
+                    "define since=\"2024-01-01\" {\n\tfields {\n\t\tname is=string\n\t}\n}\n",
+                    kind.to_string(),
+                    &TimeZone::UTC,
+                )
+                .unwrap();
+                (kind.to_string(), definition)
+            })
+            .into()
+    }
+
+    fn build(kind: &str, given: &[(&str, &str)]) -> miette::Result<NewRecord> {
+        NewRecord::build(
+            &definitions(),
+            kind,
+            None,
+            Some("2025-03-04"),
+            given
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            false,
+            &TimeZone::UTC,
+        )
+    }
+
+    #[test]
+    fn the_first_matching_route_is_used() {
+        let routes = [
+            Route {
+                kind: Some(String::from("purchase")),
+                template: String::from("{kind}/{year}-{month}-{day}.plrecs"),
+            },
+            Route {
+                kind: None,
+                template: String::from("inbox.plrecs"),
+            },
+        ];
+        let purchase = build("purchase", &[("name", "Bread")]).unwrap();
+        let note = build("note", &[("name", "Call back")]).unwrap();
+
+        assert_eq!(
+            purchase.route(&routes, "root".into()),
+            "root/purchase/2025-03-04.plrecs"
+        );
+        assert_eq!(note.route(&routes, "root".into()), "root/inbox.plrecs");
+        assert_eq!(note.route(&[], "root".into()), "root/note.plrecs");
+    }
+
+    #[test]
+    fn fields_outside_of_the_definition_are_rejected() {
+        let error = build("purchase", &[("name", "Bread"), ("store", "Corner")]).unwrap_err();
+        assert_eq!(error.to_string(), "Unknown field \"store\"");
+
+        let error = build("purchase", &[]).unwrap_err();
+        assert_eq!(error.to_string(), "The field \"name\" is missing");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn records_are_appended_once_the_file_is_unlocked() {
+        let root = tempfile::tempdir().unwrap();
+        let file = camino::Utf8Path::from_path(root.path())
+            .unwrap()
+            .join("purchase.plrecs");
+        std::fs::write(&file, "purchase \"2025-03-01\" {\n\tname \"Milk\"\n}").unwrap();
+
+        let lock = crate::edit::lock(&file).await.unwrap();
+        let append = tokio::spawn({
+            let file = file.clone();
+            async move {
+                build("purchase", &[("name", "Bread")])
+                    .unwrap()
+                    .append(&file, &definitions(), &TimeZone::UTC)
+                    .await
+            }
+        });
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!append.is_finished());
+        drop(lock);
+        append.await.unwrap().unwrap();
+
+        let written = std::fs::read_to_string(&file).unwrap();
+        assert_eq!(
+            written,
+            "purchase \"2025-03-01\" {\n\tname \"Milk\"\n}\n\npurchase \"2025-03-04\" {\n\tname \"Bread\"\n}\n"
+        );
+        assert_eq!(
+            parse_record(&written, &file, &definitions(), &TimeZone::UTC)
+                .unwrap()
+                .len(),
+            2
+        );
+        assert!(!file.with_file_name(".purchase.plrecs.lock").exists());
+    }
+}
@@ -0,0 +1,246 @@
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::hash::DefaultHasher;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::io::BufWriter;
+use std::sync::Mutex;
+
+use camino::Utf8Path;
+use camino::Utf8PathBuf;
+use jiff::tz::TimeZone;
+use tracing::debug;
+
+use crate::parsing::Definition;
+use crate::parsing::Record;
+
+/// Where the cache lives, relative to the root folder
+const CACHE_FOLDER: &str = ".plaixt/cache";
+
+/// The records parsed from every file, so that files that did not change are not parsed again
+///
+/// Records are only valid for the definitions and timezone they were parsed with, so the cache
+/// has a folder per combination of them and the version of plaixt. In there, every entry is
+/// named after the path and content of the file it was parsed from.
+///
+/// The cache only saves time, failing to read or write it is never an error.
+pub(crate) struct Cache {
+    folder: Utf8PathBuf,
+    used: Mutex<BTreeSet<String>>,
+}
+
+impl Cache {
+    /// The cache for `definitions` and `timezone`, if records can be cached for them
+    ///
+    /// Cached datetimes only keep their offset, so they are read back in the timezone named in
+    /// them. Without such a name, e.g. for a system timezone that is not known by one, the
+    /// recurrence of records would no longer follow the rules of `timezone`, and nothing is
+    /// cached.
+    pub(crate) fn open(
+        root: &Utf8Path,
+        definitions: &BTreeMap<String, Vec<Definition>>,
+        timezone: &TimeZone,
+    ) -> Option<Cache> {
+        let Some(timezone) = timezone.iana_name() else {
+            debug!(
+                ?timezone,
+                "Not caching records for a timezone without a name"
+            );
+            return None;
+        };
+        let key = hash(&(env!("CARGO_PKG_VERSION"), timezone, definitions));
+
+        Some(Cache {
+            folder: root.join(CACHE_FOLDER).join(key),
+            used: Mutex::default(),
+        })
+    }
+
+    /// The records cached for `file` with exactly this content
+    pub(crate) fn get(&self, file: &Utf8Path, bytes: &str) -> Option<Vec<Record>> {
+        let path = self.entry(file, bytes);
+        let cached = std::fs::read(&path).ok()?;
+
+        match serde_json::from_slice(&cached) {
+            Ok(records) => Some(records),
+            Err(error) => {
+                debug!(%path, %error, "Ignoring unreadable cache entry");
+                None
+            }
+        }
+    }
+
+    pub(crate) fn put(&self, file: &Utf8Path, bytes: &str, records: &[Record]) {
+        let path = self.entry(file, bytes);
+
+        if let Err(error) = self.write(&path, records) {
+            debug!(%path, %error, "Could not cache records");
+        }
+    }
+
+    /// Removes the entries of files that were not loaded, as well as the folders of other
+    /// definitions
+    pub(crate) fn prune(&self) {
+        let used = self.used.lock().unwrap();
+
+        let entries = self
+            .folder
+            .parent()
+            .and_then(|parent| parent.read_dir_utf8().ok())
+            .into_iter()
+            .flatten()
+            .chain(self.folder.read_dir_utf8().ok().into_iter().flatten())
+            .flatten();
+
+        for entry in entries {
+            let path = entry.path();
+            let result = if path == self.folder {
+                continue;
+            } else if path.parent() == Some(&self.folder) {
+                match path.file_stem() {
+                    Some(name) if used.contains(name) => continue,
+                    _ => std::fs::remove_file(path),
+                }
+            } else {
+                std::fs::remove_dir_all(path)
+            };
+
+            if let Err(error) = result {
+                debug!(%path, %error, "Could not remove stale cache entry");
+            }
+        }
+    }
+
+    fn write(&self, path: &Utf8Path, records: &[Record]) -> std::io::Result<()> {
+        std::fs::create_dir_all(&self.folder)?;
+
+        // Written next to the entry and then moved, so that nobody reads half an entry
+        let mut file = tempfile::NamedTempFile::new_in(&self.folder)?;
+        serde_json::to_writer(BufWriter::new(&mut file), records)?;
+        file.persist(path)?;
+
+        Ok(())
+    }
+
+    fn entry(&self, file: &Utf8Path, bytes: &str) -> Utf8PathBuf {
+        let name = hash(&(file, bytes));
+        let path = self.folder.join(&name).with_extension("json");
+        self.used.lock().unwrap().insert(name);
+        path
+    }
+}
+
+/// Hashes `value` for naming cache entries
+///
+/// The hasher may change between Rust releases, which only means that entries written by other
+/// builds are not found.
+fn hash(value: &impl Hash) -> String {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+/// Writes fields with the type of their value, which JSON alone could not tell apart for
+/// e.g. floats without a fraction
+pub(crate) mod kdl_values {
+    use std::collections::BTreeMap;
+
+    use kdl::KdlValue;
+    use serde::Deserialize;
+    use serde::Deserializer;
+    use serde::Serialize;
+    use serde::Serializer;
+
+    #[derive(Serialize, Deserialize)]
+    enum Value {
+        String(String),
+        Integer(i128),
+        Float(f64),
+        Bool(bool),
+        Null,
+    }
+
+    pub(crate) fn serialize<S: Serializer>(
+        fields: &BTreeMap<String, KdlValue>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(fields.iter().map(|(name, value)| {
+            let value = match value {
+                KdlValue::String(s) => Value::String(s.clone()),
+                KdlValue::Integer(i) => Value::Integer(*i),
+                KdlValue::Float(f) => Value::Float(*f),
+                KdlValue::Bool(b) => Value::Bool(*b),
+                KdlValue::Null => Value::Null,
+            };
+            (name, value)
+        }))
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<BTreeMap<String, KdlValue>, D::Error> {
+        let fields = BTreeMap::<String, Value>::deserialize(deserializer)?;
+
+        Ok(fields
+            .into_iter()
+            .map(|(name, value)| {
+                let value = match value {
+                    Value::String(s) => KdlValue::String(s),
+                    Value::Integer(i) => KdlValue::Integer(i),
+                    Value::Float(f) => KdlValue::Float(f),
+                    Value::Bool(b) => KdlValue::Bool(b),
+                    Value::Null => KdlValue::Null,
+                };
+                (name, value)
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use camino::Utf8Path;
+    use jiff::tz::TimeZone;
+
+    use super::Cache;
+    use crate::parsing::parse_definition;
+    use crate::parsing::parse_record;
+
+    #[test]
+    fn entries_follow_content_and_definitions() {
+        let root = tempfile::tempdir().unwrap();
+        let root = Utf8Path::from_path(root.path()).unwrap();
+        let file = root.join("records.plrecs");
+
+        let define = |fields: &str| {
+            let definition =
+                format!("define since=\"2024-01-01\" {{\n\tfields {{\n{fields}\t}}\n}}\n");
+            [(
+                String::from("purchase"),
+                parse_definition(&definition, String::from("purchase"), &TimeZone::UTC).unwrap(),
+            )]
+            .into()
+        };
+        let definitions = define("\t\tname is=string\n");
+        let bytes = "purchase \"2025-01-02\" {\n\tname \"Bread\"\n}\n";
+        let records = parse_record(bytes, &file, &definitions, &TimeZone::UTC).unwrap();
+
+        let cache = Cache::open(root, &definitions, &TimeZone::UTC).unwrap();
+        assert!(cache.get(&file, bytes).is_none());
+        cache.put(&file, bytes, &records);
+
+        let cached = cache.get(&file, bytes).unwrap();
+        assert_eq!(cached.len(), 1);
+        assert_eq!(cached[0].at, records[0].at);
+        assert_eq!(cached[0].fields, records[0].fields);
+        assert_eq!(cached[0].location.span, records[0].location.span);
+
+        assert!(cache.get(&file, "purchase \"2025-01-03\" {}\n").is_none());
+
+        let changed = Cache::open(root, &define("\t\tstore is=string\n"), &TimeZone::UTC).unwrap();
+        assert!(changed.get(&file, bytes).is_none());
+
+        let unnamed = TimeZone::fixed(jiff::tz::offset(-5));
+        assert!(Cache::open(root, &definitions, &unnamed).is_none());
+    }
+}
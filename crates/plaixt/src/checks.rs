@@ -0,0 +1,262 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use camino::Utf8Path;
+use camino::Utf8PathBuf;
+use futures::StreamExt;
+use futures::TryStreamExt;
+use miette::IntoDiagnostic;
+use miette::MietteDiagnostic;
+use miette::NamedSource;
+use miette::Severity;
+use tokio_stream::wrappers::ReadDirStream;
+use trustfall::FieldValue;
+
+use crate::adapter::Adapter;
+use crate::parsing::Record;
+use crate::query::header;
+use crate::query::Query;
+
+/// A query that describes a broken invariant: every row it returns is a violation.
+///
+/// Checks live in the `checks` folder of a repository, one per `.graphql` or `.trustfall` file.
+/// Leading `#` comments of the form `# message: ...` provide the message template, in which
+/// `{name}` is replaced with the output called `name` of the offending row.
+///
+/// Query variables can be given with `# arg: name = value` comments.
+///
+/// If the query outputs `_file` and `_line`, the violation is reported at that record.
+#[derive(Debug, Clone)]
+pub struct Check {
+    pub(crate) name: String,
+    pub(crate) path: Utf8PathBuf,
+    pub(crate) query: String,
+    pub(crate) message: Option<String>,
+    pub(crate) arguments: BTreeMap<Arc<str>, FieldValue>,
+}
+
+impl Check {
+    fn parse(path: Utf8PathBuf, query: String) -> Check {
+        let header = header(&query);
+
+        let message = header
+            .iter()
+            .find_map(|comment| comment.strip_prefix("message:"))
+            .map(|message| message.trim().to_string());
+
+        let arguments = header
+            .iter()
+            .filter_map(|comment| comment.strip_prefix("arg:"))
+            .filter_map(|arg| arg.split_once('='))
+            .map(|(name, value)| (Arc::from(name.trim()), parse_argument(value.trim())))
+            .collect();
+
+        Check {
+            name: path.file_stem().unwrap_or(path.as_str()).to_string(),
+            path,
+            query,
+            message,
+            arguments,
+        }
+    }
+
+    fn render_message(&self, row: &BTreeMap<Arc<str>, FieldValue>) -> String {
+        let Some(template) = &self.message else {
+            return format!("Check \"{}\" failed", self.name);
+        };
+
+        let mut message = String::new();
+        let mut rest = template.as_str();
+
+        while let Some(start) = rest.find('{') {
+            message.push_str(&rest[..start]);
+            rest = &rest[start + 1..];
+
+            let Some(end) = rest.find('}') else {
+                message.push('{');
+                break;
+            };
+
+            match row.get(&rest[..end]) {
+                Some(value) => message.push_str(&render_field_value(value)),
+                None => {
+                    message.push('{');
+                    message.push_str(&rest[..=end]);
+                }
+            }
+            rest = &rest[end + 1..];
+        }
+        message.push_str(rest);
+
+        message
+    }
+
+    /// Runs the check and returns one diagnostic per offending row
+    pub(crate) fn run(
+        &self,
+        schema: &trustfall::Schema,
+        adapter: Arc<Adapter>,
+        records: &[Arc<Record>],
+    ) -> miette::Result<Vec<miette::Report>> {
+        let rows = Query::parse(
+            schema,
+            self.path.as_str(),
+            self.query.clone(),
+            adapter.timezone(),
+        )?
+        .execute(adapter, self.arguments.clone())
+        .map_err(|e| {
+            e.with_source_code(
+                NamedSource::new(self.path.as_str(), self.query.clone()).with_language("graphql"),
+            )
+        })?
+        .collect::<Vec<_>>();
+
+        // Occurrences of a recurring record share its location, the first one stands for all
+        let mut locations = BTreeMap::new();
+        if !rows.is_empty() {
+            for record in records {
+                locations
+                    .entry((record.location.file.as_str(), record.location.line as i64))
+                    .or_insert(record);
+            }
+        }
+
+        let mut reports = vec![];
+
+        for row in rows {
+            let message = self.render_message(&row);
+
+            let diagnostic = MietteDiagnostic::new(message)
+                .with_severity(Severity::Error)
+                .with_code(self.name.clone());
+
+            let location = match (row.get("_file"), row.get("_line")) {
+                (Some(FieldValue::String(file)), Some(line)) => line
+                    .as_i64()
+                    .and_then(|line| locations.get(&(file.as_ref(), line))),
+                _ => None,
+            };
+
+            let report = match (location, row.get("_file")) {
+                (Some(record), _) => record.location.report(diagnostic),
+                (None, Some(FieldValue::String(file))) => miette::Report::from(
+                    diagnostic.with_help(format!("The offending record is in \"{file}\"")),
+                ),
+                (None, _) => miette::Report::from(diagnostic),
+            };
+
+            reports.push(report);
+        }
+
+        Ok(reports)
+    }
+}
+
+fn parse_argument(value: &str) -> FieldValue {
+    if let Ok(b) = value.parse::<bool>() {
+        FieldValue::Boolean(b)
+    } else if let Ok(i) = value.parse::<i64>() {
+        FieldValue::Int64(i)
+    } else if let Ok(f) = value.parse::<f64>() {
+        FieldValue::Float64(f)
+    } else {
+        FieldValue::String(value.trim_matches('"').into())
+    }
+}
+
+pub(crate) fn render_field_value(value: &FieldValue) -> String {
+    match value {
+        FieldValue::Null => String::from("null"),
+        FieldValue::Int64(i) => i.to_string(),
+        FieldValue::Uint64(u) => u.to_string(),
+        FieldValue::Float64(f) => f.to_string(),
+        FieldValue::String(s) | FieldValue::Enum(s) => s.to_string(),
+        FieldValue::Boolean(b) => b.to_string(),
+        FieldValue::List(values) => values
+            .iter()
+            .map(render_field_value)
+            .collect::<Vec<_>>()
+            .join(", "),
+        other => format!("{other:?}"),
+    }
+}
+
+pub(crate) async fn load_checks(path: &Utf8Path) -> miette::Result<Vec<Check>> {
+    if !tokio::fs::try_exists(path).await.into_diagnostic()? {
+        return Ok(vec![]);
+    }
+
+    let mut checks: Vec<Check> =
+        ReadDirStream::new(tokio::fs::read_dir(path).await.into_diagnostic()?)
+            .map_err(miette::Report::from_err)
+            .and_then(|entry| async move {
+                let Ok(path) = Utf8PathBuf::from_path_buf(entry.path()) else {
+                    return Ok(None);
+                };
+                let is_query = matches!(path.extension(), Some("graphql" | "trustfall"));
+                if entry.file_type().await.into_diagnostic()?.is_file() && is_query {
+                    let query = tokio::fs::read_to_string(&path).await.into_diagnostic()?;
+                    Ok(Some(Check::parse(path, query)))
+                } else {
+                    Ok(None)
+                }
+            })
+            .flat_map(|val| futures::stream::iter(val.transpose()))
+            .try_collect()
+            .await?;
+
+    checks.sort_by(|a, b| a.path.cmp(&b.path));
+
+    Ok(checks)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+
+    use trustfall::FieldValue;
+
+    use super::load_checks;
+    use super::Check;
+
+    #[test]
+    fn message_template_uses_row_outputs() {
+        let check = Check::parse(
+            "checks/receipts.graphql".into(),
+            String::from("# message: {name} costs {price} {missing}\n# arg: min = 100\n{}"),
+        );
+
+        assert_eq!(check.arguments[&Arc::from("min")], FieldValue::Int64(100));
+
+        let row = BTreeMap::from([
+            (Arc::from("name"), FieldValue::String("Nails".into())),
+            (Arc::from("price"), FieldValue::Float64(3.5)),
+        ]);
+
+        assert_eq!(check.render_message(&row), "Nails costs 3.5 {missing}");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn checks_with_names_that_are_not_utf8_are_skipped() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("receipts.graphql"), "{}").unwrap();
+        std::fs::write(
+            dir.path()
+                .join(std::ffi::OsStr::from_bytes(b"broken-\xff.graphql")),
+            "{}",
+        )
+        .unwrap();
+
+        let checks = load_checks(camino::Utf8Path::from_path(dir.path()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(checks.len(), 1);
+        assert!(checks[0].path.ends_with("receipts.graphql"));
+    }
+}
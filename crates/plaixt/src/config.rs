@@ -1,39 +1,598 @@
 use camino::Utf8Path;
 use camino::Utf8PathBuf;
+use jiff::tz::TimeZone;
 use kdl::KdlDocument;
+use kdl::KdlNode;
 use miette::Context;
 use miette::LabeledSpan;
+use miette::MietteDiagnostic;
+use miette::NamedSource;
+use miette::Severity;
 
 #[derive(Debug)]
 pub struct Config {
+    /// The file this configuration was read from
+    pub(crate) path: Utf8PathBuf,
     pub(crate) root_folder: Utf8PathBuf,
+    /// The definitions folder, relative to the root folder
+    pub(crate) definitions: Utf8PathBuf,
+    pub(crate) records: RecordGlobs,
+    /// The timezone of datetimes and dates that do not specify one
+    pub(crate) timezone: TimeZone,
+    pub(crate) output: OutputFormat,
+    pub(crate) module_directories: Vec<Utf8PathBuf>,
+    pub(crate) paperless: Option<PaperlessConfig>,
+    /// Where `plaixt add` puts new records, the first matching route wins
+    pub(crate) routes: Vec<Route>,
+    /// Problems with the configuration that do not prevent plaixt from running
+    pub(crate) warnings: Vec<miette::Report>,
 }
 
+/// Which files below the root folder are considered record files
+///
+/// Globs are matched against paths relative to the root folder.
+#[derive(Debug, Clone)]
+pub struct RecordGlobs {
+    pub(crate) include: Vec<String>,
+    pub(crate) exclude: Vec<String>,
+}
+
+impl Default for RecordGlobs {
+    fn default() -> Self {
+        Self {
+            include: vec![String::from("**/*.plrecs")],
+            exclude: vec![],
+        }
+    }
+}
+
+/// A file new records are appended to
+///
+/// The template is relative to the root folder, and may contain `{kind}`, `{year}`, `{month}`
+/// and `{day}`, which are filled in from the new record.
+#[derive(Debug, Clone)]
+pub struct Route {
+    pub(crate) kind: Option<String>,
+    pub(crate) template: String,
+}
+
+impl Route {
+    pub(crate) fn default_route() -> Route {
+        Route {
+            kind: None,
+            template: String::from("{kind}.plrecs"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    #[default]
+    Table,
+    Json,
+    Ndjson,
+    Csv,
+}
+
+#[derive(Clone)]
+pub struct PaperlessConfig {
+    pub(crate) url: String,
+    pub(crate) token: String,
+}
+
+impl std::fmt::Debug for PaperlessConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PaperlessConfig")
+            .field("url", &self.url)
+            .finish_non_exhaustive()
+    }
+}
+
+impl PaperlessConfig {
+    pub(crate) async fn client(&self) -> miette::Result<paperless_rs::PaperlessClient> {
+        paperless_rs::PaperlessClient::new(
+            &self.url,
+            paperless_rs::authorization::AuthorizationType::Token(self.token.clone()),
+            None,
+        )
+        .await
+        .map_err(|e| miette::miette!("{e}"))
+        .wrap_err_with(|| format!("Could not connect to paperless at \"{}\"", self.url))
+    }
+}
+
+pub(crate) const CONFIG_FILE_NAME: &str = "plaixt.kdl";
+
+/// Searches `start` and all of its parents for a configuration file, like git does
+pub(crate) fn find_config(start: &Utf8Path) -> miette::Result<Utf8PathBuf> {
+    start
+        .ancestors()
+        .map(|dir| dir.join(CONFIG_FILE_NAME))
+        .find(|candidate| candidate.is_file())
+        .ok_or_else(|| {
+            miette::diagnostic!(
+                help = "Pass a configuration with `--config` or set `PLAIXT_CONFIG`",
+                "Could not find \"{CONFIG_FILE_NAME}\" in \"{start}\" or any of its parents"
+            )
+            .into()
+        })
+}
+
+/// Parses the configuration at `path`
+///
+/// Relative paths inside of it are resolved against the directory containing the configuration.
 pub(crate) async fn parse_config(path: &Utf8Path) -> miette::Result<Config> {
     let data = tokio::fs::read_to_string(path)
         .await
         .map_err(|e| miette::miette!(e))
         .wrap_err_with(|| miette::miette!("Could not read configuration at \"{path}\""))?;
 
+    let source = || NamedSource::new(path.as_str(), data.clone()).with_language("kdl");
+
     let doc: KdlDocument = data
         .parse()
-        .map_err(|e| miette::Error::from(e).with_source_code(data.clone()))?;
+        .map_err(|e| miette::Error::from(e).with_source_code(source()))?;
+
+    let base = path.parent().unwrap_or(Utf8Path::new(""));
+    let mut warnings = vec![];
+
+    let mut config = parse_document(&doc, base, &mut warnings)
+        .await
+        .map_err(|e| e.with_source_code(source()))?;
+
+    config.warnings = warnings
+        .into_iter()
+        .map(|warning| miette::Report::from(warning).with_source_code(source()))
+        .collect();
+    config.path = path.to_path_buf();
+
+    Ok(config)
+}
+
+const CONFIG_KEYS: &[&str] = &[
+    "root_folder",
+    "definitions",
+    "records",
+    "timezone",
+    "output",
+    "modules",
+    "integrations",
+    "add",
+];
+
+async fn parse_document(
+    doc: &KdlDocument,
+    base: &Utf8Path,
+    warnings: &mut Vec<MietteDiagnostic>,
+) -> miette::Result<Config> {
+    for node in doc.nodes() {
+        if !CONFIG_KEYS.contains(&node.name().value()) {
+            warnings.push(unknown_key(node, CONFIG_KEYS));
+        }
+    }
+
+    let root_folder = doc
+        .get("root_folder")
+        .ok_or_else(|| miette::miette!("\"root_folder\" configuration value not found"))
+        .and_then(|val| string_argument(val, "root_folder is expected to be a path"))
+        .map(|root| base.join(root))?;
+
+    let definitions = doc
+        .get("definitions")
+        .map(|val| string_argument(val, "definitions is expected to be a path").map(Into::into))
+        .transpose()?
+        .unwrap_or_else(|| Utf8PathBuf::from("definitions"));
+
+    let records = doc
+        .get("records")
+        .map(|node| parse_record_globs(node, warnings))
+        .transpose()?
+        .unwrap_or_default();
+
+    let timezone = doc
+        .get("timezone")
+        .map(parse_timezone)
+        .transpose()?
+        .unwrap_or(TimeZone::UTC);
+
+    let output = doc
+        .get("output")
+        .map(parse_output_format)
+        .transpose()?
+        .unwrap_or_default();
+
+    let module_directories = doc
+        .get("modules")
+        .map(|node| parse_modules(node, base, warnings))
+        .transpose()?
+        .unwrap_or_default();
+
+    let paperless = match doc.get("integrations") {
+        Some(node) => parse_integrations(node, base, warnings).await?,
+        None => None,
+    };
+
+    let routes = doc
+        .get("add")
+        .map(|node| parse_routes(node, warnings))
+        .transpose()?
+        .unwrap_or_default();
 
     Ok(Config {
-        root_folder: doc
-            .get("root_folder")
-            .ok_or_else(|| miette::miette!("\"root_folder\" configuration value not found"))
-            .and_then(|val| {
-                val.get(0)
-                    .and_then(|v| v.as_string().map(Into::into))
-                    .ok_or_else(|| {
-                        miette::diagnostic!(
-                            labels = vec![LabeledSpan::new_primary_with_span(None, val.span())],
-                            "root_folder is expected to be a path"
-                        )
-                        .into()
-                    })
-                    .map_err(|e: miette::Report| e.with_source_code(data))
-            })?,
+        path: Utf8PathBuf::new(),
+        root_folder,
+        definitions,
+        records,
+        timezone,
+        output,
+        module_directories,
+        paperless,
+        routes,
+        warnings: vec![],
+    })
+}
+
+fn unknown_key(node: &KdlNode, allowed: &[&str]) -> MietteDiagnostic {
+    miette::diagnostic!(
+        severity = Severity::Warning,
+        labels = vec![LabeledSpan::new_primary_with_span(
+            Some(String::from("ignored")),
+            node.name().span()
+        )],
+        help = format!("Allowed nodes are: {}", allowed.join(", ")),
+        "Unknown configuration key \"{}\"",
+        node.name().value()
+    )
+}
+
+fn string_argument<'n>(node: &'n KdlNode, message: &str) -> miette::Result<&'n str> {
+    node.get(0).and_then(|v| v.as_string()).ok_or_else(|| {
+        miette::diagnostic!(
+            labels = vec![LabeledSpan::new_primary_with_span(None, node.span())],
+            "{message}"
+        )
+        .into()
+    })
+}
+
+fn parse_record_globs(
+    node: &KdlNode,
+    warnings: &mut Vec<MietteDiagnostic>,
+) -> miette::Result<RecordGlobs> {
+    let mut globs = RecordGlobs {
+        include: vec![],
+        exclude: vec![],
+    };
+
+    for child in node.iter_children() {
+        let target = match child.name().value() {
+            "include" => &mut globs.include,
+            "exclude" => &mut globs.exclude,
+            _ => {
+                warnings.push(unknown_key(child, &["include", "exclude"]));
+                continue;
+            }
+        };
+
+        for entry in child.entries() {
+            let Some(glob) = entry.value().as_string() else {
+                return Err(miette::diagnostic!(
+                    labels = vec![LabeledSpan::new_primary_with_span(None, entry.span())],
+                    "Record globs are expected to be strings"
+                ))?;
+            };
+
+            if let Err(e) = globset::Glob::new(glob) {
+                Err(miette::diagnostic!(
+                    labels = vec![LabeledSpan::new_primary_with_span(None, entry.span())],
+                    help = e.to_string(),
+                    "This is not a valid glob"
+                ))?;
+            }
+
+            target.push(glob.to_string());
+        }
+    }
+
+    if globs.include.is_empty() {
+        globs.include = RecordGlobs::default().include;
+    }
+
+    Ok(globs)
+}
+
+fn parse_timezone(node: &KdlNode) -> miette::Result<TimeZone> {
+    let name = string_argument(node, "timezone is expected to be an IANA timezone name")?;
+
+    let timezone = if name == "system" {
+        TimeZone::try_system()
+    } else {
+        TimeZone::get(name)
+    };
+
+    timezone.map_err(|e| {
+        miette::diagnostic!(
+            labels = vec![LabeledSpan::new_primary_with_span(None, node.span())],
+            help = e.to_string(),
+            "Unknown timezone \"{name}\""
+        )
+        .into()
+    })
+}
+
+fn parse_output_format(node: &KdlNode) -> miette::Result<OutputFormat> {
+    let name = string_argument(
+        node,
+        "output is expected to be the name of an output format",
+    )?;
+
+    <OutputFormat as clap::ValueEnum>::from_str(name, true).map_err(|_| {
+        miette::diagnostic!(
+            labels = vec![LabeledSpan::new_primary_with_span(None, node.span())],
+            help = "Allowed formats are: table, json, ndjson, csv",
+            "Unknown output format \"{name}\""
+        )
+        .into()
+    })
+}
+
+fn parse_modules(
+    node: &KdlNode,
+    base: &Utf8Path,
+    warnings: &mut Vec<MietteDiagnostic>,
+) -> miette::Result<Vec<Utf8PathBuf>> {
+    let mut directories = vec![];
+
+    for child in node.iter_children() {
+        if child.name().value() != "directory" {
+            warnings.push(unknown_key(child, &["directory"]));
+            continue;
+        }
+
+        directories.push(base.join(string_argument(
+            child,
+            "Module directories are expected to be paths",
+        )?));
+    }
+
+    Ok(directories)
+}
+
+fn parse_routes(
+    node: &KdlNode,
+    warnings: &mut Vec<MietteDiagnostic>,
+) -> miette::Result<Vec<Route>> {
+    let mut routes = vec![];
+
+    for child in node.iter_children() {
+        if child.name().value() != "route" {
+            warnings.push(unknown_key(child, &["route"]));
+            continue;
+        }
+
+        let template = string_argument(child, "Routes are expected to be a path template")?;
+        let kind = child
+            .entry("kind")
+            .map(|kind| {
+                kind.value().as_string().map(String::from).ok_or_else(|| {
+                    miette::Report::from(miette::diagnostic!(
+                        labels = vec![LabeledSpan::new_primary_with_span(None, kind.span())],
+                        "The kind of a route is expected to be a string"
+                    ))
+                })
+            })
+            .transpose()?;
+
+        routes.push(Route {
+            kind,
+            template: template.to_string(),
+        });
+    }
+
+    Ok(routes)
+}
+
+async fn parse_integrations(
+    node: &KdlNode,
+    base: &Utf8Path,
+    warnings: &mut Vec<MietteDiagnostic>,
+) -> miette::Result<Option<PaperlessConfig>> {
+    let mut paperless = None;
+
+    for child in node.iter_children() {
+        match child.name().value() {
+            "paperless" => paperless = Some(parse_paperless(child, base, warnings).await?),
+            _ => warnings.push(unknown_key(child, &["paperless"])),
+        }
+    }
+
+    Ok(paperless)
+}
+
+async fn parse_paperless(
+    node: &KdlNode,
+    base: &Utf8Path,
+    warnings: &mut Vec<MietteDiagnostic>,
+) -> miette::Result<PaperlessConfig> {
+    let mut url = None;
+    let mut token = None;
+
+    for child in node.iter_children() {
+        match child.name().value() {
+            "url" => {
+                url = Some(string_argument(
+                    child,
+                    "The paperless url is expected to be a string",
+                )?)
+            }
+            "token-file" => {
+                let file = base.join(string_argument(
+                    child,
+                    "The paperless token file is expected to be a path",
+                )?);
+                let contents = tokio::fs::read_to_string(&file).await.map_err(|e| {
+                    miette::Report::from(miette::diagnostic!(
+                        labels = vec![LabeledSpan::new_primary_with_span(None, child.span())],
+                        help = e.to_string(),
+                        "Could not read the paperless token from \"{file}\""
+                    ))
+                })?;
+                token = Some(contents.trim().to_string());
+            }
+            "token-env" => {
+                let var = string_argument(
+                    child,
+                    "The paperless token variable is expected to be a string",
+                )?;
+                let value = std::env::var(var).map_err(|e| {
+                    miette::Report::from(miette::diagnostic!(
+                        labels = vec![LabeledSpan::new_primary_with_span(None, child.span())],
+                        help = e.to_string(),
+                        "Could not read the paperless token from ${var}"
+                    ))
+                })?;
+                token = Some(value.trim().to_string());
+            }
+            _ => warnings.push(unknown_key(child, &["url", "token-file", "token-env"])),
+        }
+    }
+
+    let (Some(url), Some(token)) = (url, token) else {
+        return Err(miette::diagnostic!(
+            labels = vec![LabeledSpan::new_primary_with_span(None, node.name().span())],
+            help = "Add a `url` and either a `token-file` or `token-env`",
+            "The paperless integration is missing its url or token"
+        ))?;
+    };
+
+    Ok(PaperlessConfig {
+        url: url.trim_end_matches('/').to_string(),
+        token,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use camino::Utf8Path;
+
+    use super::find_config;
+    use super::parse_config;
+    use super::OutputFormat;
+
+    async fn parse(config: &str) -> (tempfile::TempDir, miette::Result<super::Config>) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = Utf8Path::from_path(dir.path()).unwrap().join("plaixt.kdl");
+        std::fs::write(&path, config).unwrap();
+        std::fs::write(path.with_file_name("token"), "secret\n").unwrap();
+
+        let config = parse_config(&path).await;
+        (dir, config)
+    }
+
+    #[test]
+    fn the_closest_configuration_is_found() {
+        let root = tempfile::tempdir().unwrap();
+        let root = Utf8Path::from_path(root.path()).unwrap();
+        let nested = root.join("repo/records/2025");
+        std::fs::create_dir_all(&nested).unwrap();
+
+        assert!(find_config(&nested).is_err());
+
+        std::fs::write(root.join("plaixt.kdl"), "").unwrap();
+        assert_eq!(find_config(&nested).unwrap(), root.join("plaixt.kdl"));
+
+        std::fs::write(root.join("repo/plaixt.kdl"), "").unwrap();
+        assert_eq!(find_config(&nested).unwrap(), root.join("repo/plaixt.kdl"));
+        assert_eq!(find_config(root).unwrap(), root.join("plaixt.kdl"));
+    }
+
+    #[tokio::test]
+    async fn sections_are_typed() {
+        let (dir, config) = parse(
+            r#"root_folder "./data/"
+definitions "defs"
+records {
+    include "**/*.kdl"
+    exclude "archive/**"
+    sort "name"
+}
+timezone "Europe/Berlin"
+output "csv"
+modules {
+    directory "./modules/"
+}
+add {
+    route "{kind}/{year}.plrecs" kind="purchase"
+}
+integrations {
+    paperless {
+        url "https://paperless.example.com/"
+        token-file "./token"
+    }
+}
+colour "blue"
+"#,
+        )
+        .await;
+        let config = config.unwrap();
+        let dir = Utf8Path::from_path(dir.path()).unwrap();
+
+        assert_eq!(config.root_folder, dir.join("./data/"));
+        assert_eq!(config.definitions, "defs");
+        assert_eq!(config.records.include, ["**/*.kdl"]);
+        assert_eq!(config.records.exclude, ["archive/**"]);
+        assert_eq!(config.timezone.iana_name(), Some("Europe/Berlin"));
+        assert_eq!(config.output, OutputFormat::Csv);
+        assert_eq!(config.module_directories, [dir.join("./modules/")]);
+        assert_eq!(config.routes[0].kind.as_deref(), Some("purchase"));
+
+        let paperless = config.paperless.unwrap();
+        assert_eq!(paperless.url, "https://paperless.example.com");
+        assert_eq!(paperless.token, "secret");
+
+        let warnings = config
+            .warnings
+            .iter()
+            .map(|warning| warning.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            warnings,
+            [
+                "Unknown configuration key \"colour\"",
+                "Unknown configuration key \"sort\""
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn paperless_tokens_are_read_from_the_environment() {
+        std::env::set_var("PLAIXT_TEST_PAPERLESS_TOKEN", " from-env ");
+        let (_dir, config) = parse(
+            "root_folder \".\"\nintegrations {\n    paperless {\n        url \"http://localhost\"\n        token-env \"PLAIXT_TEST_PAPERLESS_TOKEN\"\n    }\n}\n",
+        )
+        .await;
+
+        assert_eq!(config.unwrap().paperless.unwrap().token, "from-env");
+    }
+
+    #[tokio::test]
+    async fn invalid_values_are_errors() {
+        for (config, message) in [
+            ("definitions \"defs\"\n", "\"root_folder\" configuration value not found"),
+            ("root_folder \".\"\ntimezone \"Mars/Olympus\"\n", "Unknown timezone \"Mars/Olympus\""),
+            ("root_folder \".\"\noutput \"pdf\"\n", "Unknown output format \"pdf\""),
+            ("root_folder \".\"\nrecords {\n    include \"[a\"\n}\n", "This is not a valid glob"),
+            (
+                "root_folder \".\"\nintegrations {\n    paperless {\n        url \"http://localhost\"\n    }\n}\n",
+                "The paperless integration is missing its url or token",
+            ),
+            (
+                "root_folder \".\"\nintegrations {\n    paperless {\n        url \"http://localhost\"\n        token-env \"PLAIXT_TEST_UNSET_TOKEN\"\n    }\n}\n",
+                "Could not read the paperless token from $PLAIXT_TEST_UNSET_TOKEN",
+            ),
+        ] {
+            let (_dir, config) = parse(config).await;
+            assert_eq!(config.unwrap_err().to_string(), message);
+        }
+    }
+}
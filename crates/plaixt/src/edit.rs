@@ -0,0 +1,379 @@
+use std::collections::BTreeMap;
+
+use camino::Utf8Path;
+use camino::Utf8PathBuf;
+use jiff::tz::TimeZone;
+use kdl::KdlDocument;
+use kdl::KdlEntry;
+use kdl::KdlNode;
+use kdl::KdlNodeFormat;
+use kdl::KdlValue;
+use miette::IntoDiagnostic;
+use miette::NamedSource;
+
+use crate::config::RecordGlobs;
+use crate::parsing::definition_at;
+use crate::parsing::discover_record_files;
+use crate::parsing::parse_record;
+use crate::parsing::Definition;
+use crate::parsing::Record;
+
+/// How the record to edit is found
+#[derive(Debug, Clone)]
+pub enum Target {
+    /// The record with this `id` property
+    Id(String),
+    /// The record written at this line of a file
+    Line { file: Utf8PathBuf, line: usize },
+}
+
+impl Target {
+    fn matches(&self, record: &Record, bytes: &str) -> bool {
+        match self {
+            Target::Id(id) => record.id.as_deref() == Some(id.as_str()),
+            Target::Line { line, .. } => {
+                let start = record.location.line;
+                let end = start
+                    + bytes[record.location.span.offset()..][..record.location.span.len()]
+                        .matches('\n')
+                        .count();
+                (start..=end).contains(line)
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Target {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Target::Id(id) => write!(f, "id \"{id}\""),
+            Target::Line { file, line } => write!(f, "{file}:{line}"),
+        }
+    }
+}
+
+/// Parses a `file:line` or an id command line argument
+pub(crate) fn parse_target(arg: &str) -> Result<Target, String> {
+    match arg.rsplit_once(':') {
+        Some((file, line)) if !file.is_empty() => match line.parse() {
+            Ok(0) => Err(String::from("Lines are counted from 1")),
+            Ok(line) => Ok(Target::Line {
+                file: file.into(),
+                line,
+            }),
+            Err(_) => Ok(Target::Id(arg.to_string())),
+        },
+        _ => Ok(Target::Id(arg.to_string())),
+    }
+}
+
+/// What to do with the targeted record
+#[derive(Debug, Clone)]
+pub enum Change {
+    /// Set these fields, adding them if the record does not have them yet
+    Set(Vec<(String, String)>),
+    /// Remove the record, together with the comments leading up to it
+    Remove,
+}
+
+/// Finds the file the target is written in
+pub(crate) async fn find_file(
+    target: &Target,
+    root: &Utf8Path,
+    definitions_folder: &Utf8Path,
+    globs: &RecordGlobs,
+    definitions: &BTreeMap<String, Vec<Definition>>,
+    timezone: &TimeZone,
+) -> miette::Result<Utf8PathBuf> {
+    match target {
+        Target::Line { file, .. } => Ok(file.clone()),
+        Target::Id(id) => find_id(id, root, definitions_folder, globs, definitions, timezone)
+            .await?
+            .ok_or_else(|| miette::miette!("There is no record with the id \"{id}\"")),
+    }
+}
+
+/// Finds the file the record with `id` is written in, if there is one
+pub(crate) async fn find_id(
+    id: &str,
+    root: &Utf8Path,
+    definitions_folder: &Utf8Path,
+    globs: &RecordGlobs,
+    definitions: &BTreeMap<String, Vec<Definition>>,
+    timezone: &TimeZone,
+) -> miette::Result<Option<Utf8PathBuf>> {
+    let files = {
+        let root = root.to_path_buf();
+        let definitions_folder = definitions_folder.to_path_buf();
+        let globs = globs.clone();
+        tokio::task::spawn_blocking(move || {
+            discover_record_files(&root, &definitions_folder, &globs)
+        })
+        .await
+        .into_diagnostic()??
+    };
+
+    for file in files {
+        let bytes = tokio::fs::read_to_string(&file).await.into_diagnostic()?;
+        let records = parse_record(&bytes, &file, definitions, timezone)
+            .map_err(|e| e.with_source_code(NamedSource::new(&file, bytes.clone())))?;
+
+        if records.iter().any(|rec| rec.id.as_deref() == Some(id)) {
+            return Ok(Some(file));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Applies `change` to the targeted record in `file`, keeping everything else as it was written
+///
+/// The file is locked for the duration of the edit, and replaced atomically once the result
+/// has been validated against the definitions the record falls under.
+pub(crate) async fn edit_record(
+    file: &Utf8Path,
+    target: &Target,
+    change: &Change,
+    definitions: &BTreeMap<String, Vec<Definition>>,
+    timezone: &TimeZone,
+) -> miette::Result<()> {
+    let _lock = lock(file).await?;
+
+    let bytes = tokio::fs::read_to_string(file).await.into_diagnostic()?;
+    let records = parse_record(&bytes, file, definitions, timezone)
+        .map_err(|e| e.with_source_code(NamedSource::new(file, bytes.clone())))?;
+
+    let Some(record) = records.iter().find(|rec| target.matches(rec, &bytes)) else {
+        miette::bail!("There is no record at {target}");
+    };
+
+    let mut doc: KdlDocument = bytes.parse()?;
+    let index = doc
+        .nodes()
+        .iter()
+        .position(|node| node.span().offset() == record.location.span.offset())
+        .expect("a parsed record has to be a node of its document");
+
+    match change {
+        Change::Set(fields) => {
+            let definition = definition_at(&definitions[&record.kind], record.at.timestamp());
+
+            for (name, value) in fields {
+                let Some(kind) = definition.fields.get(name) else {
+                    miette::bail!(
+                        help = format!(
+                            "Fields of \"{}\" are: {}",
+                            record.kind,
+                            definition
+                                .fields
+                                .keys()
+                                .cloned()
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        ),
+                        "Unknown field \"{name}\""
+                    );
+                };
+
+                let value = kind.parse_value(value.clone());
+                kind.validate(&value).map_err(|e| {
+                    miette::miette!(help = e, "The field \"{name}\" has the wrong kind")
+                })?;
+
+                set_field(&mut doc.nodes_mut()[index], name, value);
+            }
+        }
+        Change::Remove => {
+            doc.nodes_mut().remove(index);
+        }
+    }
+
+    let edited = doc.to_string();
+    parse_record(&edited, file, definitions, timezone)
+        .map_err(|e| e.with_source_code(NamedSource::new(file, edited.clone())))?;
+
+    replace(file, &edited).await
+}
+
+fn set_field(node: &mut KdlNode, name: &str, value: KdlValue) {
+    let had_children = node.children().is_some();
+    let children = node.ensure_children();
+
+    if let Some(field) = children.get_mut(name) {
+        match field.entry_mut(0) {
+            Some(entry) => {
+                let mut repr = value.to_string();
+                entry.set_value(value);
+                if let Some(format) = entry.format_mut() {
+                    // Stay with quotes if the previous value had them
+                    if format.value_repr.starts_with('"') && !repr.starts_with('"') {
+                        repr = format!("\"{repr}\"");
+                    }
+                    format.value_repr = repr;
+                }
+            }
+            None => field.push(KdlEntry::new(value)),
+        }
+        return;
+    }
+
+    let indent = children
+        .nodes()
+        .last()
+        .and_then(|last| last.format())
+        .and_then(|format| format.leading.rsplit('\n').next())
+        .unwrap_or("\t")
+        .to_string();
+
+    let mut field = KdlNode::new(name);
+    field.push(KdlEntry::new(value));
+    field.set_format(KdlNodeFormat {
+        leading: indent,
+        terminator: String::from("\n"),
+        ..Default::default()
+    });
+    children.nodes_mut().push(field);
+
+    if !had_children {
+        node.autoformat_config(&kdl::FormatConfig::builder().indent("\t").build());
+    }
+}
+
+/// An exclusive lock for editing a file, which is released once dropped
+pub(crate) struct EditLock {
+    path: Utf8PathBuf,
+    _file: std::fs::File,
+}
+
+impl Drop for EditLock {
+    fn drop(&mut self) {
+        // Removed while still locked, so that whoever waits for it notices and locks again
+        if let Err(error) = std::fs::remove_file(&self.path) {
+            tracing::debug!(path = %self.path, %error, "Could not remove lock file");
+        }
+    }
+}
+
+/// Takes an exclusive lock for editing `file`
+///
+/// The lock is held on a hidden file next to it, as `file` itself gets replaced by edits. That
+/// file only exists while the lock is held.
+pub(crate) async fn lock(file: &Utf8Path) -> miette::Result<EditLock> {
+    let name = file.file_name().unwrap_or_default();
+    let path = file.with_file_name(format!(".{name}.lock"));
+
+    tokio::task::spawn_blocking(move || loop {
+        let lock = std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)?;
+        fs4::FileExt::lock(&lock)?;
+
+        // The previous holder removes the file it locked, which may be this one
+        if is_same_file(&lock, &path)? {
+            return Ok::<_, std::io::Error>(EditLock { path, _file: lock });
+        }
+    })
+    .await
+    .into_diagnostic()?
+    .into_diagnostic()
+}
+
+#[cfg(unix)]
+fn is_same_file(file: &std::fs::File, path: &Utf8Path) -> std::io::Result<bool> {
+    use std::os::unix::fs::MetadataExt;
+
+    let locked = file.metadata()?;
+    match std::fs::metadata(path) {
+        Ok(current) => Ok(locked.dev() == current.dev() && locked.ino() == current.ino()),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(false),
+        Err(error) => Err(error),
+    }
+}
+
+/// Files that are open cannot be removed here, so the locked file is always the current one
+#[cfg(not(unix))]
+fn is_same_file(_file: &std::fs::File, _path: &Utf8Path) -> std::io::Result<bool> {
+    Ok(true)
+}
+
+/// Replaces the contents of `file` by renaming a temporary file over it
+pub(crate) async fn replace(file: &Utf8Path, contents: &str) -> miette::Result<()> {
+    let name = file.file_name().unwrap_or_default();
+    let temporary = file.with_file_name(format!(".{name}.tmp"));
+
+    let mut handle = tokio::fs::File::create(&temporary)
+        .await
+        .into_diagnostic()?;
+    tokio::io::AsyncWriteExt::write_all(&mut handle, contents.as_bytes())
+        .await
+        .into_diagnostic()?;
+    handle.sync_all().await.into_diagnostic()?;
+    drop(handle);
+
+    tokio::fs::rename(&temporary, file).await.into_diagnostic()
+}
+
+#[cfg(test)]
+mod tests {
+    use kdl::KdlDocument;
+    use kdl::KdlValue;
+
+    use super::lock;
+    use super::parse_target;
+    use super::set_field;
+    use super::Target;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn locks_are_exclusive_and_leave_nothing_behind() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = camino::Utf8Path::from_path(dir.path()).unwrap();
+        let file = dir.join("records.plrecs");
+
+        let first = lock(&file).await.unwrap();
+        let waiting = tokio::spawn({
+            let file = file.clone();
+            async move { lock(&file).await.unwrap() }
+        });
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert!(!waiting.is_finished());
+
+        drop(first);
+        let second = waiting.await.unwrap();
+        assert!(dir.join(".records.plrecs.lock").exists());
+
+        drop(second);
+        assert_eq!(dir.read_dir_utf8().unwrap().count(), 0);
+    }
+
+    #[test]
+    fn setting_fields_keeps_formatting() {
+        let mut doc: KdlDocument =
+            "// Bought on a whim\npurchase \"2024-10-30\" {\n\tname \"Pumpkin\" // orange\n}\n"
+                .parse()
+                .unwrap();
+
+        set_field(
+            &mut doc.nodes_mut()[0],
+            "name",
+            KdlValue::String("Gourd".into()),
+        );
+        set_field(
+            &mut doc.nodes_mut()[0],
+            "count",
+            KdlValue::String("5".into()),
+        );
+
+        assert_eq!(
+            doc.to_string(),
+            "// Bought on a whim\npurchase \"2024-10-30\" {\n\tname \"Gourd\" // orange\n\tcount \"5\"\n}\n"
+        );
+
+        assert!(matches!(
+            parse_target("shopping.plrecs:12"),
+            Ok(Target::Line { line: 12, .. })
+        ));
+        assert!(matches!(parse_target("pumpkin"), Ok(Target::Id(_))));
+    }
+}
@@ -0,0 +1,345 @@
+use std::collections::BTreeMap;
+
+use camino::Utf8Path;
+use camino::Utf8PathBuf;
+use jiff::tz::TimeZone;
+use kdl::FormatConfig;
+use kdl::KdlDocument;
+use kdl::KdlEntry;
+use kdl::KdlEntryFormat;
+use kdl::KdlNode;
+use kdl::KdlValue;
+use miette::IntoDiagnostic;
+use miette::NamedSource;
+
+use crate::parsing::definition_at;
+use crate::parsing::format_timestamp;
+use crate::parsing::parse_record;
+use crate::parsing::parse_timestamp;
+use crate::parsing::Definition;
+
+/// All files are indented with tabs, one per level
+const FORMAT: FormatConfig<'static> = FormatConfig::builder().indent("\t").build();
+
+/// Formats a record file
+///
+/// Records are sorted chronologically and their fields ordered as declared in their
+/// definition. With `timestamps`, datetimes are rewritten in a single style, keeping their
+/// precision and timezone.
+pub(crate) fn format_records(
+    bytes: &str,
+    file: &Utf8Path,
+    definitions: &BTreeMap<String, Vec<Definition>>,
+    timezone: &TimeZone,
+    timestamps: bool,
+) -> miette::Result<String> {
+    let records = parse_record(bytes, file, definitions, timezone)?;
+    let mut doc: KdlDocument = bytes.parse()?;
+
+    let mut nodes = records
+        .into_iter()
+        .zip(std::mem::take(doc.nodes_mut()))
+        .collect::<Vec<_>>();
+    nodes.sort_by(|(a, _), (b, _)| a.cmp_time(b));
+
+    for (record, node) in &mut nodes {
+        if timestamps {
+            let at = format_timestamp(&record.at, record.precision, timezone);
+            if let Some(entry) = node.entry_mut(0) {
+                entry.set_value(at);
+            }
+
+            if let Some(end) = node.entry_mut("end") {
+                if let Some(Ok((written, precision))) = end
+                    .value()
+                    .as_string()
+                    .map(|end| parse_timestamp(end, timezone))
+                {
+                    end.set_value(format_timestamp(&written, precision, timezone));
+                }
+            }
+        }
+
+        let definition = definition_at(&definitions[&record.kind], record.at.timestamp());
+        format_record(node, definition);
+    }
+
+    *doc.nodes_mut() = nodes.into_iter().map(|(_, node)| node).collect();
+    separate_nodes(&mut doc);
+
+    Ok(doc.to_string())
+}
+
+/// Formats a single record node, ordering its fields as they are declared in `definition`
+pub(crate) fn format_record(node: &mut KdlNode, definition: &Definition) {
+    if let Some(children) = node.children_mut() {
+        children.nodes_mut().sort_by_key(|field| {
+            definition
+                .order
+                .iter()
+                .position(|name| name == field.name().value())
+                .unwrap_or(usize::MAX)
+        });
+    }
+
+    normalize_entries(node, &|entry| match entry.value() {
+        value @ KdlValue::String(_) => quoted(value),
+        value => written(entry).unwrap_or_else(|| value.to_string()),
+    });
+
+    let mut comments = vec![];
+    line_comments(std::slice::from_ref(node), &mut comments);
+    node.autoformat_config(&FORMAT);
+    restore_line_comments(std::slice::from_mut(node), &mut comments.into_iter());
+}
+
+/// Formats a definition file, keeping its values as they are written
+pub(crate) fn format_definitions(bytes: &str) -> miette::Result<String> {
+    let mut doc: KdlDocument = bytes.parse()?;
+
+    for node in doc.nodes_mut() {
+        normalize_entries(node, &|entry| {
+            written(entry).unwrap_or_else(|| entry.value().to_string())
+        });
+    }
+
+    let mut comments = vec![];
+    line_comments(doc.nodes(), &mut comments);
+    doc.autoformat_config(&FORMAT);
+    restore_line_comments(doc.nodes_mut(), &mut comments.into_iter());
+    separate_nodes(&mut doc);
+
+    Ok(doc.to_string())
+}
+
+/// Formats the given files, or only reports the ones that are not formatted with `check`
+///
+/// Files in `definitions_folder` are formatted as definitions, all others as records.
+/// Returns the files that were, or would be, changed.
+pub(crate) async fn format_files(
+    files: &[Utf8PathBuf],
+    definitions_folder: &Utf8Path,
+    definitions: &BTreeMap<String, Vec<Definition>>,
+    timezone: &TimeZone,
+    timestamps: bool,
+    check: bool,
+) -> miette::Result<Vec<Utf8PathBuf>> {
+    let mut changed = vec![];
+    // Files may be given relative to the working directory, or with `..` in them
+    let definitions_folder = definitions_folder
+        .canonicalize_utf8()
+        .unwrap_or_else(|_| definitions_folder.to_path_buf());
+
+    for file in files {
+        let _lock = match check {
+            true => None,
+            false => Some(crate::edit::lock(file).await?),
+        };
+
+        let bytes = tokio::fs::read_to_string(file).await.into_diagnostic()?;
+
+        let is_definition = file
+            .canonicalize_utf8()
+            .is_ok_and(|file| file.starts_with(&definitions_folder));
+        let formatted = if is_definition {
+            format_definitions(&bytes)
+        } else {
+            format_records(&bytes, file, definitions, timezone, timestamps)
+        }
+        .map_err(|e| {
+            e.with_source_code(NamedSource::new(file, bytes.clone()).with_language("kdl"))
+        })?;
+
+        if formatted == bytes {
+            continue;
+        }
+
+        if !check {
+            crate::edit::replace(file, &formatted).await?;
+        }
+        changed.push(file.clone());
+    }
+
+    Ok(changed)
+}
+
+/// Puts every value on the same line as its node, separated by a single space
+fn normalize_entries(node: &mut KdlNode, repr: &impl Fn(&KdlEntry) -> String) {
+    for entry in node.entries_mut() {
+        let value_repr = repr(entry);
+        entry.set_format(KdlEntryFormat {
+            value_repr,
+            leading: String::from(" "),
+            autoformat_keep: true,
+            ..Default::default()
+        });
+    }
+
+    for child in node.iter_children_mut() {
+        normalize_entries(child, repr);
+    }
+}
+
+/// The comment at the end of the line of every node, in the order they are written
+///
+/// Such a comment is kept with the terminator of its node, which autoformatting replaces.
+fn line_comments(nodes: &[KdlNode], comments: &mut Vec<Option<String>>) {
+    for node in nodes {
+        let comment = node
+            .format()
+            .map(|format| {
+                let terminator = format.terminator.trim_start_matches(';');
+                format!("{} {}", format.before_terminator.trim(), terminator.trim())
+            })
+            .map(|comment| comment.trim().to_string())
+            .filter(|comment| !comment.is_empty());
+        comments.push(comment);
+
+        if let Some(children) = node.children() {
+            line_comments(children.nodes(), comments);
+        }
+    }
+}
+
+/// Puts the comments taken by [`line_comments`] back after autoformatting
+fn restore_line_comments(
+    nodes: &mut [KdlNode],
+    comments: &mut impl Iterator<Item = Option<String>>,
+) {
+    for node in nodes {
+        if let (Some(Some(comment)), Some(format)) = (comments.next(), node.format_mut()) {
+            format.before_terminator = String::from(" ");
+            format.terminator = format!("{comment}\n");
+        }
+
+        if let Some(children) = node.children_mut() {
+            restore_line_comments(children.nodes_mut(), comments);
+        }
+    }
+}
+
+/// How the value of `entry` was written, if it was read from a file
+fn written(entry: &KdlEntry) -> Option<String> {
+    entry.format().map(|format| format.value_repr.clone())
+}
+
+/// Writes a string value in quotes, even if it would be a valid identifier
+fn quoted(value: &KdlValue) -> String {
+    let repr = value.to_string();
+    match repr.starts_with('"') || repr.starts_with('#') {
+        true => repr,
+        false => format!("\"{repr}\""),
+    }
+}
+
+/// Separates top level nodes by an empty line
+fn separate_nodes(doc: &mut KdlDocument) {
+    for node in doc.nodes_mut().iter_mut().skip(1) {
+        if let Some(format) = node.format_mut() {
+            format.leading.insert(0, '\n');
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use jiff::tz::TimeZone;
+
+    use super::format_definitions;
+    use super::format_files;
+    use super::format_records;
+    use crate::parsing::parse_definition;
+
+    #[tokio::test]
+    async fn definitions_are_recognized_by_relative_paths() {
+        let root = tempfile::tempdir().unwrap();
+        let root = camino::Utf8Path::from_path(root.path()).unwrap();
+        std::fs::create_dir(root.join("definitions")).unwrap();
+        std::fs::write(
+            root.join("definitions/purchase.pldef"),
+            "define since=\"2024-01-01\" {\n    fields {\n        name is=string\n    }\n}\n",
+        )
+        .unwrap();
+
+        // The same file, relative to the working directory
+        let cwd = camino::Utf8PathBuf::from_path_buf(std::env::current_dir().unwrap()).unwrap();
+        let mut relative = camino::Utf8PathBuf::new();
+        for _ in cwd.components().skip(1) {
+            relative.push("..");
+        }
+        relative.push(root.strip_prefix("/").unwrap());
+        relative.push("definitions/purchase.pldef");
+
+        let changed = format_files(
+            &[relative.clone()],
+            &root.join("definitions"),
+            &Default::default(),
+            &TimeZone::UTC,
+            false,
+            true,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(changed, [relative]);
+    }
+
+    #[test]
+    fn records_are_sorted_and_keep_comments() {
+        let definitions = [(
+            String::from("purchase"),
+            parse_definition(
+                "define since=\"2024-01-01\" { fields { name is=string; store is=string } }",
+                String::from("purchase"),
+                &TimeZone::UTC,
+            )
+            .unwrap(),
+        )]
+        .into();
+
+        let formatted = format_records(
+            "// Nails\npurchase \"2024-11-05T10:00:00+01:00\" {\n  store DIYCo\n      name \"Nails\"\n}\npurchase \"2024-10-30\" {\n    name Pumpkin; store FarmerBernard }\n",
+            "shopping.plrecs".into(),
+            &definitions,
+            &TimeZone::UTC,
+            true,
+        )
+        .unwrap();
+
+        assert_eq!(
+            formatted,
+            "purchase \"2024-10-30\" {\n\tname \"Pumpkin\"\n\tstore \"FarmerBernard\"\n}\n\n// Nails\npurchase \"2024-11-05 10:00:00+01:00\" {\n\tname \"Nails\"\n\tstore \"DIYCo\"\n}\n"
+        );
+    }
+
+    #[test]
+    fn comments_at_the_end_of_lines_are_kept() {
+        let definition = format_definitions(
+            "define since=\"2024-01-01\" {\n  fields {\n    name is=string // What was bought\n    store  is=string\n  }\n}\n",
+        )
+        .unwrap();
+        assert_eq!(
+            definition,
+            "define since=\"2024-01-01\" {\n\tfields {\n\t\tname is=string // What was bought\n\t\tstore is=string\n\t}\n}\n"
+        );
+
+        let definition =
+            parse_definition(&definition, String::from("purchase"), &TimeZone::UTC).unwrap();
+        assert_eq!(definition[0].field_descriptions["name"], "What was bought");
+
+        let definitions = [(String::from("purchase"), definition)].into();
+        let formatted = format_records(
+            "purchase \"2024-10-30\" {\n    store FarmerBernard /* at the market */\n  name Pumpkin // For soup\n}\n",
+            "shopping.plrecs".into(),
+            &definitions,
+            &TimeZone::UTC,
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(
+            formatted,
+            "purchase \"2024-10-30\" {\n\tname \"Pumpkin\" // For soup\n\tstore \"FarmerBernard\" /* at the market */\n}\n"
+        );
+    }
+}
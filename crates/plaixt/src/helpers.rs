@@ -0,0 +1,164 @@
+use std::ffi::OsString;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::ExitStatus;
+use std::sync::Arc;
+
+use camino::Utf8Path;
+use camino::Utf8PathBuf;
+use kdl::KdlValue;
+use miette::IntoDiagnostic;
+
+use crate::parsing::Record;
+
+/// Finds the executable run for `plaixt <name>`, like git finds `git-<name>`
+///
+/// Helpers are called `plaixt-<name>`, and are looked up in the module directories of the
+/// configuration before the directories in `PATH`.
+pub(crate) fn find_helper(
+    name: &str,
+    module_directories: &[Utf8PathBuf],
+) -> miette::Result<PathBuf> {
+    let file_name = format!("plaixt-{name}");
+
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    let directories = module_directories
+        .iter()
+        .map(|dir| dir.as_std_path().to_path_buf())
+        .chain(std::env::split_paths(&path));
+
+    for directory in directories {
+        let candidate = directory.join(&file_name);
+        if is_executable(&candidate) {
+            return Ok(candidate);
+        }
+    }
+
+    miette::bail!(
+        help = format!(
+            "Saved queries go into the `queries` folder, other helpers are executables called \"{file_name}\" in a module directory or on PATH"
+        ),
+        "Unknown command \"{name}\""
+    )
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    path.metadata()
+        .is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// Runs a helper with `args`, telling it about the repository through environment variables
+///
+/// - `PLAIXT_ROOT` is the root folder of the repository
+/// - `PLAIXT_CONFIG` is the configuration in use
+/// - `PLAIXT_SCHEMA` is a file with the schema queries are written against
+/// - `PLAIXT_RECORDS` is a file with a JSON array of all records
+///
+/// The files are removed once the helper exits.
+pub(crate) async fn run_helper(
+    helper: &Path,
+    args: &[OsString],
+    root_folder: &Utf8Path,
+    config: &Utf8Path,
+    schema: &str,
+    records: &[Arc<Record>],
+) -> miette::Result<ExitStatus> {
+    let export = tempfile::Builder::new()
+        .prefix("plaixt-")
+        .tempdir()
+        .into_diagnostic()?;
+
+    let schema_path = export.path().join("schema.graphql");
+    tokio::fs::write(&schema_path, schema)
+        .await
+        .into_diagnostic()?;
+
+    let records_path = export.path().join("records.json");
+    let records = records
+        .iter()
+        .map(|record| record_json(record))
+        .collect::<Vec<_>>();
+    let records = serde_json::to_vec(&records).into_diagnostic()?;
+    tokio::fs::write(&records_path, records)
+        .await
+        .into_diagnostic()?;
+
+    let status = tokio::process::Command::new(helper)
+        .args(args)
+        .env("PLAIXT_ROOT", root_folder)
+        .env("PLAIXT_CONFIG", config)
+        .env("PLAIXT_SCHEMA", &schema_path)
+        .env("PLAIXT_RECORDS", &records_path)
+        .status()
+        .await
+        .into_diagnostic()
+        .map_err(|e| e.wrap_err(format!("Could not run {}", helper.display())))?;
+
+    export.close().into_diagnostic()?;
+
+    Ok(status)
+}
+
+/// A record as helpers see it, with datetimes written as RFC 9557 strings
+fn record_json(record: &Record) -> serde_json::Value {
+    let fields = record
+        .fields
+        .iter()
+        .map(|(name, value)| (name.clone(), kdl_json(value)))
+        .collect::<serde_json::Map<_, _>>();
+
+    serde_json::json!({
+        "kind": record.kind,
+        "id": record.id,
+        "at": record.at.to_string(),
+        "end": record.until.as_ref().map(|until| until.to_string()),
+        "file": record.location.file,
+        "line": record.location.line,
+        "fields": fields,
+    })
+}
+
+fn kdl_json(value: &KdlValue) -> serde_json::Value {
+    match value {
+        KdlValue::String(s) => s.clone().into(),
+        KdlValue::Integer(i) => match i64::try_from(*i) {
+            Ok(i) => i.into(),
+            // Larger integers than JSON numbers can safely hold are kept exact as strings
+            Err(_) => i.to_string().into(),
+        },
+        KdlValue::Float(f) => (*f).into(),
+        KdlValue::Bool(b) => (*b).into(),
+        KdlValue::Null => serde_json::Value::Null,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use camino::Utf8PathBuf;
+
+    use super::find_helper;
+
+    #[cfg(unix)]
+    #[test]
+    fn helpers_are_found_in_module_directories() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let modules = tempfile::tempdir().unwrap();
+        let helper = modules.path().join("plaixt-warranty");
+        std::fs::write(&helper, "#!/bin/sh\n").unwrap();
+
+        let directories = [Utf8PathBuf::from_path_buf(modules.path().to_path_buf()).unwrap()];
+        assert!(find_helper("warranty", &directories).is_err());
+
+        std::fs::set_permissions(&helper, std::fs::Permissions::from_mode(0o755)).unwrap();
+        assert_eq!(find_helper("warranty", &directories).unwrap(), helper);
+    }
+}
@@ -1,47 +1,182 @@
 #![allow(dead_code)]
 
 use std::collections::BTreeMap;
+use std::ffi::OsString;
 use std::io::Read;
+use std::path::PathBuf;
 use std::sync::Arc;
 
+use adapter::RecordStore;
+use camino::Utf8Path;
 use camino::Utf8PathBuf;
+use clap::CommandFactory;
+use clap::FromArgMatches;
 use clap::Parser;
 use clap::Subcommand;
 use clap::ValueHint;
 use human_panic::Metadata;
+use jiff::tz::TimeZone;
+use miette::Context;
 use miette::IntoDiagnostic;
 use parsing::Definition;
+use parsing::InvalidRecord;
 use parsing::Record;
 use tracing::info;
 use tracing_subscriber::EnvFilter;
-use trustfall::execute_query;
-use trustfall::FieldValue;
 
 mod adapter;
+mod add;
+mod cache;
+mod checks;
 mod config;
+mod edit;
+mod fmt;
+mod helpers;
+mod output;
 mod parsing;
+mod progress;
+mod query;
+mod recurrence;
+mod repl;
+mod saved;
+mod watch;
 
 #[derive(Debug, Parser)]
+#[command(subcommand_required = true, allow_external_subcommands = true)]
 struct Args {
     #[arg(short, long, value_hint(ValueHint::DirPath))]
     root_folder: Option<Utf8PathBuf>,
 
-    #[arg(
-        short,
-        long,
-        value_hint(ValueHint::FilePath),
-        default_value_t = Utf8PathBuf::from("plaixt.kdl")
-    )]
-    config: Utf8PathBuf,
+    /// The configuration to use, per default the first `plaixt.kdl` found in the current
+    /// directory or its parents
+    #[arg(short, long, value_hint(ValueHint::FilePath), env = "PLAIXT_CONFIG")]
+    config: Option<Utf8PathBuf>,
 
+    /// Only expand recurring records into occurrences from this date on
+    #[arg(long, global = true)]
+    recur_from: Option<String>,
+
+    /// Expand recurring records into occurrences up to this date, per default up to now
+    #[arg(long, global = true)]
+    recur_until: Option<String>,
+
+    /// Parse all files again, instead of reusing the records cached in `.plaixt` for files
+    /// that did not change
+    #[arg(long, global = true)]
+    no_cache: bool,
+
+    /// Load records that are not valid as `InvalidRecord`s, instead of stopping at the first
+    #[arg(long, global = true)]
+    lenient: bool,
+
+    /// Empty for saved queries, which are added as subcommands at runtime, and for helpers
     #[command(subcommand)]
-    mode: ArgMode,
+    mode: Option<ArgMode>,
 }
 
 #[derive(Debug, Subcommand)]
 enum ArgMode {
     Dump,
-    Query,
+    /// Run a query and write its results to stdout
+    Query {
+        /// The file to read the query from, per default stdin
+        #[arg(value_hint(ValueHint::FilePath))]
+        file: Option<Utf8PathBuf>,
+
+        /// Values of the variables the query declares, written as `name=value`
+        #[arg(long = "var", value_parser = add::parse_assignment)]
+        variables: Vec<(String, String)>,
+
+        /// How to write the results, per default the `output` of the configuration
+        #[arg(short, long)]
+        output: Option<config::OutputFormat>,
+
+        /// Run the query again whenever records or definitions change
+        #[arg(long)]
+        watch: bool,
+    },
+    /// Run all queries in the `checks` folder and report every row they return
+    Check {
+        /// Run the checks again whenever records or definitions change
+        #[arg(long)]
+        watch: bool,
+    },
+    /// Append a new record, asking for every field that is not given
+    Add {
+        /// The kind of record to add
+        kind: String,
+
+        /// Values of the fields, written as `field=value`
+        #[arg(value_parser = add::parse_assignment)]
+        fields: Vec<(String, String)>,
+
+        /// When the record happened, per default now
+        #[arg(long)]
+        at: Option<String>,
+
+        /// A unique id to refer to the record by
+        #[arg(long)]
+        id: Option<String>,
+
+        /// Append to this file instead of the one chosen by the `add` routes
+        #[arg(long, value_hint(ValueHint::FilePath))]
+        file: Option<Utf8PathBuf>,
+
+        /// Never ask for fields, use their defaults or fail instead
+        #[arg(long)]
+        no_input: bool,
+    },
+    /// Change fields of a record in place, keeping the formatting of its file
+    Set {
+        /// The record to change, either its id or a `file:line` inside of it
+        #[arg(value_parser = edit::parse_target)]
+        target: edit::Target,
+
+        /// New values of the fields, written as `field=value`
+        #[arg(value_parser = add::parse_assignment, required = true)]
+        fields: Vec<(String, String)>,
+    },
+    /// Remove a record, keeping the formatting of its file
+    Remove {
+        /// The record to remove, either its id or a `file:line` inside of it
+        #[arg(value_parser = edit::parse_target)]
+        target: edit::Target,
+    },
+    /// Format record and definition files
+    Fmt {
+        /// The files to format, per default all record and definition files
+        #[arg(value_hint(ValueHint::FilePath))]
+        files: Vec<Utf8PathBuf>,
+
+        /// Only list the files that are not formatted, and fail if there are any
+        #[arg(long)]
+        check: bool,
+
+        /// Also write all datetimes in the same style
+        #[arg(long)]
+        timestamps: bool,
+    },
+    /// Run queries interactively, keeping the records loaded in between
+    Repl,
+    /// Print the schema queries are written against
+    Schema {
+        /// Write the schema to this file instead, e.g. for editors and GraphQL tooling
+        #[arg(long, value_hint(ValueHint::FilePath))]
+        export: Option<Utf8PathBuf>,
+    },
+    /// A query from the `queries` folder
+    #[command(skip)]
+    Saved {
+        saved: saved::SavedQuery,
+        variables: Vec<(String, String)>,
+        output: Option<config::OutputFormat>,
+    },
+    /// A `plaixt-<name>` executable run as `plaixt <name>`
+    #[command(skip)]
+    Helper {
+        helper: PathBuf,
+        args: Vec<OsString>,
+    },
 }
 
 #[tokio::main]
@@ -56,48 +191,593 @@ async fn main() -> miette::Result<()> {
         .pretty()
         .init();
 
-    let args = Args::parse();
+    // Saved queries become subcommands, so the repository has to be found before parsing
+    let location = Args::command()
+        .ignore_errors(true)
+        .disable_help_flag(true)
+        .disable_help_subcommand(true)
+        .disable_version_flag(true)
+        .try_get_matches()
+        .ok();
+    let config = load_config(
+        location
+            .as_ref()
+            .and_then(|location| location.get_one::<Utf8PathBuf>("config").cloned()),
+    )
+    .await;
+
+    let mut saved_warnings = vec![];
+    let saved_queries = match &config {
+        Ok(config) => {
+            let root_folder = location
+                .as_ref()
+                .and_then(|location| location.get_one::<Utf8PathBuf>("root_folder"))
+                .unwrap_or(&config.root_folder);
+            saved::load_saved_queries(
+                &root_folder.join("queries"),
+                &saved::reserved_parameters(&Args::command()),
+                &mut saved_warnings,
+            )
+            .await
+        }
+        // Help should still be shown without a repository
+        Err(_) => vec![],
+    };
+
+    let mut command = Args::command();
+    for saved in &saved_queries {
+        if command.find_subcommand(&saved.name).is_some() {
+            eprintln!(
+                "{:?}",
+                miette::miette!(
+                    severity = miette::Severity::Warning,
+                    "The saved query {} is hidden by the built-in command of the same name",
+                    saved.path
+                )
+            );
+            continue;
+        }
+        command = command.subcommand(saved.command());
+    }
+
+    let matches = command.get_matches();
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+
+    let config = config?;
+    for warning in config.warnings.iter().chain(&saved_warnings) {
+        eprintln!("{warning:?}");
+    }
 
-    let config = config::parse_config(&args.config).await?;
     let root_folder = args.root_folder.as_ref().unwrap_or(&config.root_folder);
 
-    let definitions = parsing::load_definitions(&root_folder.join("definitions")).await?;
+    let definitions =
+        parsing::load_definitions(&root_folder.join(&config.definitions), &config.timezone).await?;
+    for warning in adapter::entrypoint_warnings(definitions.keys()) {
+        eprintln!("{warning:?}");
+    }
 
-    let records = parsing::load_records(root_folder, &definitions).await?;
+    let options = parsing::LoadOptions {
+        window: recurrence_window(&args, &config.timezone)?,
+        cache: !args.no_cache,
+        lenient: args.lenient,
+    };
 
-    let (schema, adapter) = get_schema_and_adapter(&definitions, records.clone());
+    let mode = match args.mode {
+        Some(mode) => mode,
+        None => {
+            let (name, matches) = matches
+                .subcommand()
+                .expect("a subcommand is required to run plaixt");
 
-    match args.mode {
-        ArgMode::Query => {
-            let mut query = String::new();
-            std::io::stdin()
-                .read_to_string(&mut query)
-                .into_diagnostic()?;
+            match saved_queries.iter().find(|saved| saved.name == name) {
+                Some(saved) => {
+                    let (variables, output) = saved.arguments(matches);
+                    ArgMode::Saved {
+                        saved: saved.clone(),
+                        variables,
+                        output,
+                    }
+                }
+                None => ArgMode::Helper {
+                    helper: helpers::find_helper(name, &config.module_directories)?,
+                    args: matches
+                        .get_many::<OsString>("")
+                        .unwrap_or_default()
+                        .cloned()
+                        .collect(),
+                },
+            }
+        }
+    };
+
+    let Some(mode) = run_before_loading(mode, root_folder, &config, &definitions, &options).await?
+    else {
+        return Ok(());
+    };
 
-            let result = execute_query(
-                &schema,
+    let (records, schema, adapter) =
+        load_queryable(root_folder, &config, &definitions, &options).await?;
+
+    match mode {
+        LoadedMode::Query {
+            file,
+            variables,
+            output,
+        } => {
+            let (name, text) = read_query(file).await?;
+            let query = query::Query::parse(&schema, name, text, &config.timezone)?;
+            run_query(
+                &query,
+                variables,
+                output.unwrap_or(config.output),
                 Arc::new(adapter),
+                &config.timezone,
+            )?;
+        }
+        LoadedMode::Saved {
+            saved,
+            variables,
+            output,
+        } => {
+            let query =
+                query::Query::parse(&schema, saved.path.as_str(), saved.query, &config.timezone)?;
+            run_query(
                 &query,
-                BTreeMap::<Arc<str>, FieldValue>::from([]),
+                variables,
+                output.unwrap_or(config.output),
+                Arc::new(adapter),
+                &config.timezone,
+            )?;
+        }
+        LoadedMode::Dump => {
+            print_records(records.records(), &definitions);
+        }
+        LoadedMode::Helper { helper, args } => {
+            let status = helpers::run_helper(
+                &helper,
+                &args,
+                root_folder,
+                &config.path,
+                &adapter::schema_text(&definitions),
+                records.records(),
             )
-            .unwrap()
-            .collect::<Vec<_>>();
+            .await?;
 
-            info!("Got records: {result:#?}");
+            if !status.success() {
+                std::process::exit(status.code().unwrap_or(1));
+            }
         }
-        ArgMode::Dump => {
-            print_records(&records);
+        LoadedMode::Check => {
+            let checks = checks::load_checks(&root_folder.join("checks")).await?;
+            let loaded = watch::Loaded {
+                records,
+                schema,
+                adapter: Arc::new(adapter),
+            };
+
+            let failures = run_checks(&checks, &loaded)?;
+            if failures > 0 {
+                miette::bail!(
+                    "{failures} problem(s) found by {count} check(s)",
+                    count = checks.len()
+                );
+            }
+
+            info!("All {} checks passed", checks.len());
         }
     }
 
     Ok(())
 }
 
+/// What runs once all records are loaded
+enum LoadedMode {
+    Query {
+        file: Option<Utf8PathBuf>,
+        variables: Vec<(String, String)>,
+        output: Option<config::OutputFormat>,
+    },
+    Saved {
+        saved: saved::SavedQuery,
+        variables: Vec<(String, String)>,
+        output: Option<config::OutputFormat>,
+    },
+    Dump,
+    Helper {
+        helper: PathBuf,
+        args: Vec<OsString>,
+    },
+    Check,
+}
+
+/// Runs the modes that do not need all records loaded, and hands back the others
+///
+/// Writing records only needs the definitions, so that broken records elsewhere can be fixed.
+/// The REPL and watching load records themselves, so that they can load them again.
+async fn run_before_loading(
+    mode: ArgMode,
+    root_folder: &Utf8Path,
+    config: &config::Config,
+    definitions: &BTreeMap<String, Vec<Definition>>,
+    options: &parsing::LoadOptions,
+) -> miette::Result<Option<LoadedMode>> {
+    match mode {
+        ArgMode::Add {
+            kind,
+            fields,
+            at,
+            id,
+            file,
+            no_input,
+        } => {
+            let record = add::NewRecord::build(
+                definitions,
+                &kind,
+                id,
+                at.as_deref(),
+                fields,
+                !no_input,
+                &config.timezone,
+            )?;
+
+            if let Some(id) = &record.id {
+                let existing = edit::find_id(
+                    id,
+                    root_folder,
+                    &config.definitions,
+                    &config.records,
+                    definitions,
+                    &config.timezone,
+                )
+                .await?;
+
+                if let Some(existing) = existing {
+                    miette::bail!("The id \"{id}\" is already used in {existing}");
+                }
+            }
+
+            let file = file.unwrap_or_else(|| record.route(&config.routes, root_folder));
+            record.append(&file, definitions, &config.timezone).await?;
+
+            eprintln!("Added {kind} to {file}");
+        }
+        ArgMode::Set { target, fields } => {
+            edit_record(
+                root_folder,
+                config,
+                definitions,
+                target,
+                edit::Change::Set(fields),
+            )
+            .await?;
+        }
+        ArgMode::Remove { target } => {
+            edit_record(
+                root_folder,
+                config,
+                definitions,
+                target,
+                edit::Change::Remove,
+            )
+            .await?;
+        }
+        ArgMode::Fmt {
+            files,
+            check,
+            timestamps,
+        } => {
+            let definitions_folder = root_folder.join(&config.definitions);
+            let files = match files.is_empty() {
+                true => all_files(root_folder, config).await?,
+                false => files,
+            };
+
+            let changed = fmt::format_files(
+                &files,
+                &definitions_folder,
+                definitions,
+                &config.timezone,
+                timestamps,
+                check,
+            )
+            .await?;
+
+            for file in &changed {
+                println!("{file}");
+            }
+
+            if check && !changed.is_empty() {
+                miette::bail!("{} file(s) are not formatted", changed.len());
+            }
+        }
+        ArgMode::Schema { export } => {
+            let schema = adapter::schema_text(definitions);
+            match export {
+                Some(file) => {
+                    tokio::fs::write(&file, schema)
+                        .await
+                        .into_diagnostic()
+                        .wrap_err_with(|| format!("Could not write the schema to {file}"))?;
+                    eprintln!("Wrote the schema to {file}");
+                }
+                None => print!("{schema}"),
+            }
+        }
+        ArgMode::Repl => repl::run(root_folder, config, options).await?,
+        ArgMode::Query {
+            file,
+            variables,
+            output,
+            watch: true,
+        } => {
+            let (name, text) = read_query(file).await?;
+            let output = output.unwrap_or(config.output);
+
+            watch::watch(root_folder, config, options, |loaded| {
+                let ran = query::Query::parse(
+                    &loaded.schema,
+                    name.as_str(),
+                    text.as_str(),
+                    &config.timezone,
+                )
+                .and_then(|query| {
+                    run_query(
+                        &query,
+                        variables.clone(),
+                        output,
+                        loaded.adapter,
+                        &config.timezone,
+                    )
+                });
+                std::future::ready(ran)
+            })
+            .await?;
+        }
+        ArgMode::Check { watch: true } => {
+            let checks_folder = root_folder.join("checks");
+            watch::watch(root_folder, config, options, |loaded| {
+                let checks_folder = checks_folder.clone();
+                async move {
+                    // Checks may change while watching as well
+                    let checks = checks::load_checks(&checks_folder).await?;
+                    let failures = run_checks(&checks, &loaded)?;
+                    eprintln!(
+                        "{failures} problem(s) found by {count} check(s)",
+                        count = checks.len()
+                    );
+                    Ok(())
+                }
+            })
+            .await?;
+        }
+        ArgMode::Query {
+            file,
+            variables,
+            output,
+            watch: false,
+        } => {
+            return Ok(Some(LoadedMode::Query {
+                file,
+                variables,
+                output,
+            }))
+        }
+        ArgMode::Saved {
+            saved,
+            variables,
+            output,
+        } => {
+            return Ok(Some(LoadedMode::Saved {
+                saved,
+                variables,
+                output,
+            }))
+        }
+        ArgMode::Dump => return Ok(Some(LoadedMode::Dump)),
+        ArgMode::Helper { helper, args } => return Ok(Some(LoadedMode::Helper { helper, args })),
+        ArgMode::Check { watch: false } => return Ok(Some(LoadedMode::Check)),
+    }
+
+    Ok(None)
+}
+
+async fn edit_record(
+    root_folder: &Utf8Path,
+    config: &config::Config,
+    definitions: &BTreeMap<String, Vec<Definition>>,
+    target: edit::Target,
+    change: edit::Change,
+) -> miette::Result<()> {
+    let file = edit::find_file(
+        &target,
+        root_folder,
+        &config.definitions,
+        &config.records,
+        definitions,
+        &config.timezone,
+    )
+    .await?;
+
+    edit::edit_record(&file, &target, &change, definitions, &config.timezone).await?;
+
+    eprintln!("Changed {file}");
+    Ok(())
+}
+
+/// All definition and record files of the repository
+async fn all_files(
+    root_folder: &Utf8Path,
+    config: &config::Config,
+) -> miette::Result<Vec<Utf8PathBuf>> {
+    let mut files = vec![];
+
+    let mut definitions = tokio::fs::read_dir(root_folder.join(&config.definitions))
+        .await
+        .into_diagnostic()?;
+    while let Some(entry) = definitions.next_entry().await.into_diagnostic()? {
+        let hidden = entry.file_name().to_string_lossy().starts_with('.');
+        if entry.file_type().await.into_diagnostic()?.is_file() && !hidden {
+            files.push(
+                Utf8PathBuf::from_path_buf(entry.path()).map_err(|path| {
+                    miette::miette!("Path is not valid UTF-8: {}", path.display())
+                })?,
+            );
+        }
+    }
+    files.sort();
+
+    let root_folder = root_folder.to_path_buf();
+    let definitions_folder = config.definitions.clone();
+    let globs = config.records.clone();
+    files.extend(
+        tokio::task::spawn_blocking(move || {
+            parsing::discover_record_files(&root_folder, &definitions_folder, &globs)
+        })
+        .await
+        .into_diagnostic()??,
+    );
+
+    Ok(files)
+}
+
+/// Finds the configuration like git finds its repository, unless a path is given
+async fn load_config(path: Option<Utf8PathBuf>) -> miette::Result<config::Config> {
+    let path = match path {
+        Some(path) => path,
+        None => {
+            let cwd = std::env::current_dir().into_diagnostic()?;
+            let cwd = Utf8PathBuf::from_path_buf(cwd)
+                .map_err(|cwd| miette::miette!("Path is not valid UTF-8: {}", cwd.display()))?;
+            config::find_config(&cwd)?
+        }
+    };
+
+    config::parse_config(&path).await
+}
+
+/// Reads the query to run from `file`, or from stdin if there is none
+async fn read_query(file: Option<Utf8PathBuf>) -> miette::Result<(String, String)> {
+    match file {
+        Some(file) if file != "-" => {
+            let text = tokio::fs::read_to_string(&file)
+                .await
+                .into_diagnostic()
+                .wrap_err_with(|| format!("Could not read the query {file}"))?;
+            Ok((file.to_string(), text))
+        }
+        _ => {
+            let mut text = String::new();
+            std::io::stdin()
+                .read_to_string(&mut text)
+                .into_diagnostic()?;
+            Ok((String::from("stdin"), text))
+        }
+    }
+}
+
+/// Runs `query` and writes its rows to stdout
+fn run_query(
+    query: &query::Query,
+    variables: Vec<(String, String)>,
+    output: config::OutputFormat,
+    adapter: Arc<adapter::Adapter>,
+    timezone: &TimeZone,
+) -> miette::Result<()> {
+    let variables = query.variables(variables, timezone)?;
+    let rows = query.execute(adapter, variables)?;
+
+    let written = output::write_rows(
+        output,
+        &query.columns(),
+        rows,
+        &mut std::io::stdout().lock(),
+    );
+
+    match written {
+        // The reader, e.g. `head`, has seen enough
+        Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => Ok(()),
+        written => written.into_diagnostic(),
+    }
+}
+
+/// Runs `checks`, printing every problem they find, and returns how many there are
+fn run_checks(checks: &[checks::Check], loaded: &watch::Loaded) -> miette::Result<usize> {
+    let mut failures = 0;
+    for check in checks {
+        for report in check.run(
+            &loaded.schema,
+            loaded.adapter.clone(),
+            loaded.records.records(),
+        )? {
+            failures += 1;
+            eprintln!("{report:?}");
+        }
+    }
+
+    Ok(failures)
+}
+
+fn recurrence_window(args: &Args, timezone: &TimeZone) -> miette::Result<recurrence::Window> {
+    let parse = |value: &str| {
+        parsing::parse_timestamp(value, timezone)
+            .map(|(at, _)| at.timestamp())
+            .wrap_err_with(|| format!("Could not parse \"{value}\" as a datetime"))
+    };
+
+    Ok(recurrence::Window {
+        from: args.recur_from.as_deref().map(parse).transpose()?,
+        until: match args.recur_until.as_deref() {
+            Some(until) => parse(until)?,
+            None => recurrence::Window::default().until,
+        },
+    })
+}
+
+/// Loads all records and makes them queryable
+async fn load_queryable(
+    root_folder: &Utf8Path,
+    config: &config::Config,
+    definitions: &BTreeMap<String, Vec<Definition>>,
+    options: &parsing::LoadOptions,
+) -> miette::Result<(Arc<RecordStore>, trustfall::Schema, adapter::Adapter)> {
+    let (records, invalid) = parsing::load_records(
+        root_folder,
+        &config.definitions,
+        &config.records,
+        definitions,
+        &config.timezone,
+        options,
+    )
+    .await?;
+
+    warn_invalid(&invalid);
+    let records = RecordStore::new(records, invalid);
+    let (schema, adapter) = get_schema_and_adapter(
+        definitions,
+        records.clone(),
+        config.paperless.clone(),
+        config.timezone.clone(),
+    )?;
+    Ok((records, schema, adapter))
+}
+
+/// Points out that records were loaded leniently, as they would otherwise go unnoticed
+fn warn_invalid(invalid: &[InvalidRecord]) {
+    if !invalid.is_empty() {
+        eprintln!(
+            "Loaded {} invalid record(s), query them through InvalidRecords",
+            invalid.len()
+        );
+    }
+}
+
 fn get_schema_and_adapter(
     definitions: &BTreeMap<String, Vec<Definition>>,
-    records: Vec<Record>,
-) -> (trustfall::Schema, adapter::Adapter) {
-    let schema = adapter::to_schema(definitions);
+    records: Arc<RecordStore>,
+    paperless: Option<config::PaperlessConfig>,
+    timezone: TimeZone,
+) -> miette::Result<(trustfall::Schema, adapter::Adapter)> {
+    let schema = adapter::to_schema(definitions)?;
     let definitions = definitions
         .iter()
         .map(|(name, def)| (name.clone(), def.last().cloned().unwrap().fields))
@@ -106,17 +786,43 @@ fn get_schema_and_adapter(
         schema.clone(),
         records,
         definitions,
-        None,
+        paperless,
+        timezone,
         tokio::runtime::Handle::current(),
     );
-    (schema, adapter)
+    Ok((schema, adapter))
 }
 
-fn print_records(records: &[Record]) {
+fn print_records(records: &[Arc<Record>], definitions: &BTreeMap<String, Vec<Definition>>) {
     for record in records {
-        println!("{kind} @ {at} {{", kind = record.kind, at = record.at);
+        let definition = definitions
+            .get(&record.kind)
+            .map(|defs| parsing::definition_at(defs, record.at.timestamp()));
+
+        if let Some(description) = definition.and_then(|def| def.description.as_ref()) {
+            for line in description.lines() {
+                println!("// {line}");
+            }
+        }
+
+        match &record.until {
+            Some(end) => println!(
+                "{kind} @ {at} until {end} {{",
+                kind = record.kind,
+                at = record.at
+            ),
+            None => println!("{kind} @ {at} {{", kind = record.kind, at = record.at),
+        }
         for field in &record.fields {
-            println!("\t{name} = {value}", name = field.0, value = field.1);
+            let description = definition
+                .and_then(|def| def.field_descriptions.get(field.0))
+                .map(|description| format!(" // {}", description.replace('\n', " ")))
+                .unwrap_or_default();
+            println!(
+                "\t{name} = {value}{description}",
+                name = field.0,
+                value = field.1
+            );
         }
         println!("}}")
     }
@@ -125,6 +831,7 @@ fn print_records(records: &[Record]) {
 #[cfg(test)]
 mod tests {
     use camino::Utf8PathBuf;
+    use jiff::tz::TimeZone;
     use tracing_subscriber::EnvFilter;
     use trustfall::provider::check_adapter_invariants;
 
@@ -142,15 +849,29 @@ mod tests {
         let root_folder = Utf8PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../../examples");
 
         println!("{root_folder}");
-        let definitions = parsing::load_definitions(&root_folder.join("definitions"))
-            .await
-            .unwrap();
+        let definitions =
+            parsing::load_definitions(&root_folder.join("definitions"), &TimeZone::UTC)
+                .await
+                .unwrap();
 
-        let records = parsing::load_records(&root_folder, &definitions)
-            .await
-            .unwrap();
+        let (records, invalid) = parsing::load_records(
+            &root_folder,
+            "definitions".into(),
+            &Default::default(),
+            &definitions,
+            &TimeZone::UTC,
+            &parsing::LoadOptions::default(),
+        )
+        .await
+        .unwrap();
 
-        let (schema, adapter) = get_schema_and_adapter(&definitions, records.clone());
+        let (schema, adapter) = get_schema_and_adapter(
+            &definitions,
+            crate::adapter::RecordStore::new(records, invalid),
+            None,
+            TimeZone::UTC,
+        )
+        .unwrap();
 
         check_adapter_invariants(&schema, adapter);
     }
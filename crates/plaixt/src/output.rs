@@ -0,0 +1,276 @@
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::io::Write;
+use std::sync::Arc;
+
+use async_graphql_parser::types::Selection;
+use async_graphql_parser::types::SelectionSet;
+use async_graphql_parser::Positioned;
+use async_graphql_value::Value;
+use trustfall::FieldValue;
+use trustfall::TransparentValue;
+
+use crate::config::OutputFormat;
+
+/// Orders the outputs of a query as their `@output` directives are written in it
+///
+/// Outputs whose name can not be traced back to the query text come last.
+pub(crate) fn output_order(
+    query: &str,
+    outputs: impl IntoIterator<Item = Arc<str>>,
+) -> Vec<Arc<str>> {
+    fn walk(selections: &Positioned<SelectionSet>, prefix: &str, names: &mut Vec<String>) {
+        for selection in &selections.node.items {
+            let field = match &selection.node {
+                Selection::Field(field) => &field.node,
+                Selection::InlineFragment(fragment) => {
+                    walk(&fragment.node.selection_set, prefix, names);
+                    continue;
+                }
+                Selection::FragmentSpread(_) => continue,
+            };
+
+            let local = field.alias.as_ref().unwrap_or(&field.name).node.as_str();
+
+            for directive in &field.directives {
+                if directive.node.name.node != "output" {
+                    continue;
+                }
+
+                match directive.node.get_argument("name").map(|name| &name.node) {
+                    Some(Value::String(name)) => names.push(name.clone()),
+                    _ => {
+                        names.push(format!("{prefix}{local}"));
+                        // Folds that are counted are named after their edge
+                        names.push(format!("{prefix}{local}_count"));
+                    }
+                }
+            }
+
+            let prefix = match &field.alias {
+                Some(alias) => format!("{prefix}{}", alias.node),
+                None => prefix.to_string(),
+            };
+            walk(&field.selection_set, &prefix, names);
+        }
+    }
+
+    let mut names = vec![];
+    if let Ok(document) = async_graphql_parser::parse_query(query) {
+        for (_, operation) in document.operations.iter() {
+            // The root edge does not prefix output names, even if it has an alias
+            for root in &operation.node.selection_set.node.items {
+                if let Selection::Field(field) = &root.node {
+                    walk(&field.node.selection_set, "", &mut names);
+                }
+            }
+        }
+    }
+
+    let mut remaining: BTreeSet<Arc<str>> = outputs.into_iter().collect();
+    let mut ordered: Vec<Arc<str>> = names
+        .iter()
+        .filter_map(|name| remaining.take(name.as_str()))
+        .collect();
+    ordered.extend(remaining);
+
+    ordered
+}
+
+/// Writes all rows to `out`, with one column per output in `columns`
+pub(crate) fn write_rows(
+    format: OutputFormat,
+    columns: &[Arc<str>],
+    rows: impl Iterator<Item = BTreeMap<Arc<str>, FieldValue>>,
+    out: &mut impl Write,
+) -> std::io::Result<()> {
+    match format {
+        OutputFormat::Table => write_table(columns, rows, out),
+        OutputFormat::Json => {
+            let rows = rows.map(|row| json_row(columns, row)).collect::<Vec<_>>();
+            serde_json::to_writer_pretty(&mut *out, &rows)?;
+            writeln!(out)
+        }
+        OutputFormat::Ndjson => {
+            for row in rows {
+                serde_json::to_writer(&mut *out, &json_row(columns, row))?;
+                writeln!(out)?;
+            }
+            Ok(())
+        }
+        OutputFormat::Csv => {
+            let header = columns
+                .iter()
+                .map(|column| csv_field(column))
+                .collect::<Vec<_>>();
+            writeln!(out, "{}", header.join(","))?;
+
+            for row in rows {
+                let fields = columns
+                    .iter()
+                    .map(
+                        |column| match row.get(column).unwrap_or(&FieldValue::Null) {
+                            FieldValue::Null => String::new(),
+                            value @ FieldValue::List(_) => {
+                                csv_field(&json_value(value).to_string())
+                            }
+                            value => csv_field(&render_cell(value)),
+                        },
+                    )
+                    .collect::<Vec<_>>();
+                writeln!(out, "{}", fields.join(","))?;
+            }
+            Ok(())
+        }
+    }
+}
+
+fn write_table(
+    columns: &[Arc<str>],
+    rows: impl Iterator<Item = BTreeMap<Arc<str>, FieldValue>>,
+    out: &mut impl Write,
+) -> std::io::Result<()> {
+    let cells = rows
+        .map(|row| {
+            columns
+                .iter()
+                .map(
+                    |column| match row.get(column).unwrap_or(&FieldValue::Null) {
+                        FieldValue::Null => String::new(),
+                        value => render_cell(value).replace('\n', "\\n"),
+                    },
+                )
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    let widths = columns
+        .iter()
+        .enumerate()
+        .map(|(idx, column)| {
+            cells
+                .iter()
+                .map(|row| row[idx].chars().count())
+                .chain([column.chars().count()])
+                .max()
+                .unwrap_or_default()
+        })
+        .collect::<Vec<_>>();
+
+    let mut write_line = |cells: &mut dyn Iterator<Item = String>| {
+        let line = cells
+            .zip(&widths)
+            .map(|(cell, width)| format!("{cell:width$}"))
+            .collect::<Vec<_>>()
+            .join("  ");
+        writeln!(out, "{}", line.trim_end())
+    };
+
+    write_line(&mut columns.iter().map(|column| column.to_string()))?;
+    write_line(&mut widths.iter().map(|width| "-".repeat(*width)))?;
+    for row in cells {
+        write_line(&mut row.into_iter())?;
+    }
+
+    Ok(())
+}
+
+/// Renders a value for a table cell, lists of folded outputs are put in brackets
+fn render_cell(value: &FieldValue) -> String {
+    match value {
+        FieldValue::Null => String::from("null"),
+        FieldValue::List(values) => format!(
+            "[{}]",
+            values
+                .iter()
+                .map(render_cell)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        value => crate::checks::render_field_value(value),
+    }
+}
+
+fn json_value(value: &FieldValue) -> serde_json::Value {
+    serde_json::to_value(TransparentValue::from(value.clone())).unwrap_or(serde_json::Value::Null)
+}
+
+fn json_row(columns: &[Arc<str>], row: BTreeMap<Arc<str>, FieldValue>) -> serde_json::Value {
+    columns
+        .iter()
+        .map(|column| {
+            let value = row.get(column).map(json_value).unwrap_or_default();
+            (column.to_string(), value)
+        })
+        .collect::<serde_json::Map<_, _>>()
+        .into()
+}
+
+/// Quotes a CSV field if needed, as described in RFC 4180
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+
+    use trustfall::FieldValue;
+
+    use super::output_order;
+    use super::write_rows;
+    use crate::config::OutputFormat;
+
+    #[test]
+    fn columns_follow_the_query() {
+        let query = r#"{ Records { ... on p_changelog { title @output _at @output(name: "when") entries: _source @fold { version @output } } } }"#;
+        let columns = output_order(
+            query,
+            ["entriesversion", "title", "when"].map(Arc::<str>::from),
+        );
+        assert_eq!(
+            columns,
+            ["title", "when", "entriesversion"].map(Arc::<str>::from)
+        );
+
+        let row = BTreeMap::from([
+            (Arc::from("title"), FieldValue::from("Trip, again")),
+            (Arc::from("when"), FieldValue::Null),
+            (
+                Arc::from("entriesversion"),
+                FieldValue::List(Arc::from([FieldValue::from("0.1"), FieldValue::Null])),
+            ),
+        ]);
+
+        let mut csv = vec![];
+        write_rows(
+            OutputFormat::Csv,
+            &columns,
+            [row.clone()].into_iter(),
+            &mut csv,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "title,when,entriesversion\n\"Trip, again\",,\"[\"\"0.1\"\",null]\"\n"
+        );
+
+        let mut ndjson = vec![];
+        write_rows(
+            OutputFormat::Ndjson,
+            &columns,
+            [row].into_iter(),
+            &mut ndjson,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(ndjson).unwrap(),
+            "{\"title\":\"Trip, again\",\"when\":null,\"entriesversion\":[\"0.1\",null]}\n"
+        );
+    }
+}
@@ -1,162 +1,881 @@
 use std::collections::BTreeMap;
+use std::num::NonZero;
+use std::sync::Arc;
 
 use camino::Utf8Path;
 use camino::Utf8PathBuf;
 use futures::StreamExt;
 use futures::TryStreamExt;
+use globset::Glob;
+use globset::GlobSet;
+use globset::GlobSetBuilder;
+use ignore::WalkBuilder;
+use jiff::civil::Date;
 use jiff::fmt::temporal::DateTimeParser;
+use jiff::tz::TimeZone;
+use jiff::Span;
 use jiff::Timestamp;
+use jiff::Zoned;
 use kdl::KdlDocument;
+use kdl::KdlNode;
 use kdl::KdlValue;
 use miette::IntoDiagnostic;
 use miette::LabeledSpan;
 use miette::NamedSource;
+use miette::SourceSpan;
 use owo_colors::OwoColorize;
+use serde::Deserialize;
+use serde::Serialize;
 use tokio_stream::wrappers::ReadDirStream;
+use tracing::debug;
 
-#[derive(Debug, Clone)]
+use crate::cache::Cache;
+use crate::config::RecordGlobs;
+use crate::progress::Progress;
+use crate::recurrence;
+use crate::recurrence::RecurrenceRule;
+use crate::recurrence::Window;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Record {
     pub(crate) kind: String,
-    pub(crate) at: Timestamp,
+    /// The unique id given with an `id` property
+    pub(crate) id: Option<String>,
+    /// When this record starts
+    pub(crate) at: Zoned,
+    pub(crate) precision: Precision,
+    /// The exclusive end given with an `end` or `duration` property
+    pub(crate) until: Option<Zoned>,
+    /// How this record repeats, given with a `repeat` property
+    pub(crate) recurrence: Option<RecurrenceRule>,
+    /// The written record this one is an occurrence of, if it is one
+    ///
+    /// Occurrences are only created after parsing, so they are never cached.
+    #[serde(skip)]
+    pub(crate) source: Option<Arc<Record>>,
+    pub(crate) location: Location,
+    #[serde(with = "crate::cache::kdl_values")]
     pub(crate) fields: BTreeMap<String, KdlValue>,
 }
 
-pub(crate) fn parse_timestamp(value: &str) -> miette::Result<Timestamp> {
+impl Record {
+    /// The exclusive end of this record
+    ///
+    /// Records without an explicit end last for their precision, e.g. the whole month for
+    /// "2019-06". Records with a full datetime and no end are instants and have no end.
+    pub(crate) fn end(&self) -> Option<Zoned> {
+        self.until
+            .clone()
+            .or_else(|| self.precision.end_of(&self.at))
+    }
+
+    /// Orders records by their start, and records starting together by their end
+    ///
+    /// Instants come before periods starting at the same time.
+    pub(crate) fn cmp_time(&self, other: &Record) -> std::cmp::Ordering {
+        self.at
+            .cmp(&other.at)
+            .then_with(|| match (self.end(), other.end()) {
+                (None, None) => std::cmp::Ordering::Equal,
+                (None, Some(_)) => std::cmp::Ordering::Less,
+                (Some(_), None) => std::cmp::Ordering::Greater,
+                (Some(a), Some(b)) => a.cmp(&b),
+            })
+    }
+
+    /// Whether both records share at least one instant
+    pub(crate) fn overlaps(&self, other: &Record) -> bool {
+        let contains = |rec: &Record, at: &Zoned| match rec.end() {
+            Some(end) => rec.at <= *at && *at < end,
+            None => rec.at == *at,
+        };
+
+        contains(self, &other.at) || contains(other, &self.at)
+    }
+}
+
+/// How precisely the start of a record is known
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Precision {
+    Year,
+    Month,
+    Day,
+    Time,
+}
+
+impl Precision {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Precision::Year => "year",
+            Precision::Month => "month",
+            Precision::Day => "day",
+            Precision::Time => "time",
+        }
+    }
+
+    /// The end of the period starting at `start` with this precision, if it is one
+    pub(crate) fn end_of(&self, start: &Zoned) -> Option<Zoned> {
+        let span = match self {
+            Precision::Year => Span::new().years(1),
+            Precision::Month => Span::new().months(1),
+            Precision::Day => Span::new().days(1),
+            Precision::Time => return None,
+        };
+
+        start.checked_add(span).ok()
+    }
+}
+
+/// Where in the repository a record was written down
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Location {
+    pub(crate) file: Utf8PathBuf,
+    pub(crate) span: SourceSpan,
+    /// 1-based line of the start of the span
+    pub(crate) line: usize,
+    /// 1-based column of the start of the span, counted in characters
+    pub(crate) column: usize,
+}
+
+impl Location {
+    pub(crate) fn new(file: &Utf8Path, bytes: &str, span: SourceSpan) -> Location {
+        let before = &bytes[..span.offset()];
+        let line_start = before.rfind('\n').map(|idx| idx + 1).unwrap_or(0);
+
+        Location {
+            file: file.to_path_buf(),
+            span,
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+        }
+    }
+
+    /// Turns a diagnostic into one pointing at this location, with the file as its source
+    pub(crate) fn report(&self, diagnostic: miette::MietteDiagnostic) -> miette::Report {
+        let diagnostic = diagnostic.and_label(LabeledSpan::new_primary_with_span(
+            Some(String::from("this record")),
+            self.span,
+        ));
+
+        match std::fs::read_to_string(&self.file) {
+            Ok(source) => miette::Report::from(diagnostic).with_source_code(
+                NamedSource::new(self.file.as_str(), source).with_language("kdl"),
+            ),
+            Err(_) => miette::Report::from(diagnostic)
+                .wrap_err(format!("at {}:{}:{}", self.file, self.line, self.column)),
+        }
+    }
+}
+
+/// A record that could not be loaded, kept when loading leniently so that it can be queried
+#[derive(Debug, Clone)]
+pub struct InvalidRecord {
+    /// The kind of the record, unless its file could not be read at all
+    pub(crate) kind: Option<String>,
+    /// When this record starts, if that could be read
+    pub(crate) at: Option<Zoned>,
+    /// The fields as written, whether they are valid or not
+    pub(crate) fields: BTreeMap<String, KdlValue>,
+    /// What is wrong with the record
+    pub(crate) errors: Vec<String>,
+    pub(crate) location: Location,
+}
+
+impl InvalidRecord {
+    fn new(
+        node: &KdlNode,
+        bytes: &str,
+        file: &Utf8Path,
+        timezone: &TimeZone,
+        errors: Vec<miette::Report>,
+    ) -> InvalidRecord {
+        let at = node
+            .get(0)
+            .and_then(|at| at.as_string())
+            .and_then(|at| parse_timestamp(at, timezone).ok())
+            .map(|(at, _)| at);
+
+        let fields = node
+            .iter_children()
+            .filter_map(|field| Some((field.name().value().to_string(), field.get(0)?.clone())))
+            .collect();
+
+        InvalidRecord {
+            kind: Some(node.name().value().to_string()),
+            at,
+            fields,
+            errors: errors.iter().flat_map(error_messages).collect(),
+            location: Location::new(file, bytes, node.span()),
+        }
+    }
+
+    /// The whole file, which is not valid KDL
+    fn unreadable(file: &Utf8Path, bytes: &str, error: miette::Report) -> InvalidRecord {
+        let span = error
+            .related()
+            .into_iter()
+            .flatten()
+            .chain([error.as_ref()])
+            .find_map(|diagnostic| diagnostic.labels()?.next())
+            .map_or_else(|| SourceSpan::from(0..0), |label| *label.inner());
+
+        InvalidRecord {
+            kind: None,
+            at: None,
+            fields: BTreeMap::new(),
+            errors: error_messages(&error),
+            location: Location::new(file, bytes, span),
+        }
+    }
+}
+
+/// The messages of a diagnostic and the ones related to it, with their help
+fn error_messages(error: &miette::Report) -> Vec<String> {
+    let related = error.related().into_iter().flatten().collect::<Vec<_>>();
+    let diagnostics = match related.is_empty() {
+        true => vec![error.as_ref()],
+        false => related,
+    };
+
+    diagnostics
+        .into_iter()
+        .map(|diagnostic| match diagnostic.help() {
+            Some(help) => format!("{diagnostic} {help}"),
+            None => diagnostic.to_string(),
+        })
+        .collect()
+}
+
+/// The records of one file, and when loading leniently the ones that could not be loaded
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ParsedFile {
+    pub(crate) records: Vec<Record>,
+    pub(crate) invalid: Vec<InvalidRecord>,
+}
+
+/// How records are loaded
+#[derive(Debug, Clone, Default)]
+pub(crate) struct LoadOptions {
+    /// Recurring records are expanded into their occurrences inside of this window
+    pub(crate) window: Window,
+    /// Whether records parsed from files that did not change are reused
+    pub(crate) cache: bool,
+    /// Whether records that are not valid are kept as [`InvalidRecord`]s instead of failing
+    pub(crate) lenient: bool,
+}
+
+/// Parses a datetime, keeping the timezone or offset it was written with
+///
+/// Datetimes and dates without an offset are interpreted in `timezone`. Dates, as well as
+/// partial dates like "2019-06" or "2019", start at midnight of their first day.
+pub(crate) fn parse_timestamp(
+    value: &str,
+    timezone: &TimeZone,
+) -> miette::Result<(Zoned, Precision)> {
     let parser = DateTimeParser::new();
 
+    if let Some(partial) = parse_partial_date(value) {
+        return partial
+            .and_then(|(date, precision)| Ok((date.to_zoned(timezone.clone())?, precision)))
+            .into_diagnostic();
+    }
+
     parser
-        .parse_timestamp(value)
-        .or_else(|_| {
-            parser
-                .parse_datetime(value)
-                .and_then(|date| date.in_tz("UTC").map(|z| z.timestamp()))
-        })
+        .parse_zoned(value)
+        .map(|zoned| (zoned, Precision::Time))
         .or_else(|_| {
-            parser
-                .parse_date(value)
-                .and_then(|date| date.in_tz("UTC").map(|z| z.timestamp()))
+            let pieces = parser.parse_pieces(value)?;
+            let precision = match pieces.time() {
+                Some(_) => Precision::Time,
+                None => Precision::Day,
+            };
+            let datetime = pieces.date().to_datetime(pieces.time().unwrap_or_default());
+
+            let timezone = match (pieces.to_time_zone()?, pieces.to_numeric_offset()) {
+                (Some(timezone), _) => timezone,
+                (None, Some(offset)) => TimeZone::fixed(offset),
+                (None, None) => timezone.clone(),
+            };
+
+            Ok::<_, jiff::Error>((datetime.to_zoned(timezone)?, precision))
         })
         .into_diagnostic()
 }
 
+/// Parses dates only precise to the year ("2019") or month ("2019-06")
+fn parse_partial_date(value: &str) -> Option<Result<(Date, Precision), jiff::Error>> {
+    let is_number = |part: &str| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit());
+
+    match value.split_once('-') {
+        None if value.len() == 4 && is_number(value) => {
+            Some(Date::new(value.parse().ok()?, 1, 1).map(|date| (date, Precision::Year)))
+        }
+        Some((year, month)) if year.len() == 4 && is_number(year) && is_number(month) => Some(
+            Date::new(year.parse().ok()?, month.parse().ok()?, 1)
+                .map(|date| (date, Precision::Month)),
+        ),
+        _ => None,
+    }
+}
+
+/// Writes a datetime the way `parse_timestamp` reads it back, keeping its precision
+///
+/// The timezone is only named if it differs from `timezone`, the offset is always written.
+pub(crate) fn format_timestamp(at: &Zoned, precision: Precision, timezone: &TimeZone) -> String {
+    match precision {
+        Precision::Year => at.strftime("%Y").to_string(),
+        Precision::Month => at.strftime("%Y-%m").to_string(),
+        Precision::Day => at.strftime("%Y-%m-%d").to_string(),
+        Precision::Time => {
+            let written = at.strftime("%Y-%m-%d %H:%M:%S%.f%:z").to_string();
+            match at.time_zone().iana_name() {
+                Some(name) if timezone.iana_name() != Some(name) => format!("{written}[{name}]"),
+                _ => written,
+            }
+        }
+    }
+}
+
+/// Parses the optional `end` or `duration` property of a record into its exclusive end
+///
+/// Like the start, the end may be a partial date, in which case the whole period is included.
+fn parse_record_end(
+    node: &KdlNode,
+    start: &Zoned,
+    timezone: &TimeZone,
+) -> miette::Result<Option<Zoned>> {
+    let until = match (node.entry("end"), node.entry("duration")) {
+        (None, None) => return Ok(None),
+        (Some(end), Some(duration)) => Err(miette::diagnostic!(
+            labels = vec![
+                LabeledSpan::new_primary_with_span(None, end.span()),
+                LabeledSpan::new_with_span(None, duration.span()),
+            ],
+            "A record can either have an `end` or a `duration`, not both."
+        ))?,
+        (Some(end), None) => end
+            .value()
+            .as_string()
+            .and_then(|end| parse_timestamp(end, timezone).ok())
+            .map(|(end, precision)| precision.end_of(&end).unwrap_or(end))
+            .ok_or_else(|| {
+                miette::diagnostic!(
+                    labels = vec![LabeledSpan::new_primary_with_span(None, end.span())],
+                    "The `end` property should be a string formatted as RFC3339."
+                )
+            })
+            .map(|until| (until, end.span()))?,
+        (None, Some(duration)) => duration
+            .value()
+            .as_string()
+            .and_then(|duration| duration.parse::<Span>().ok())
+            .and_then(|duration| start.checked_add(duration).ok())
+            .ok_or_else(|| {
+                miette::diagnostic!(
+                    labels = vec![LabeledSpan::new_primary_with_span(None, duration.span())],
+                    help = "Durations are written like \"P2W\" or \"2 weeks\"",
+                    "The `duration` property should be a string with a positive duration."
+                )
+            })
+            .map(|until| (until, duration.span()))?,
+    };
+
+    match until {
+        (until, span) if until <= *start => Err(miette::diagnostic!(
+            labels = vec![LabeledSpan::new_primary_with_span(None, span)],
+            "A record has to end after it starts."
+        ))?,
+        (until, _) => Ok(Some(until)),
+    }
+}
+
 pub(crate) fn parse_record(
     bytes: &str,
+    file: &Utf8Path,
     definitions: &BTreeMap<String, Vec<Definition>>,
+    timezone: &TimeZone,
 ) -> miette::Result<Vec<Record>> {
     let doc: KdlDocument = bytes.parse()?;
 
-    let mut recs = vec![];
+    doc.nodes()
+        .iter()
+        .map(|node| {
+            parse_record_node(node, bytes, file, definitions, timezone)
+                .map_err(|mut errors| errors.remove(0))
+        })
+        .collect()
+}
+
+/// Like [`parse_record`], but keeps the records that are not valid as [`InvalidRecord`]s
+pub(crate) fn parse_record_leniently(
+    bytes: &str,
+    file: &Utf8Path,
+    definitions: &BTreeMap<String, Vec<Definition>>,
+    timezone: &TimeZone,
+) -> ParsedFile {
+    let doc = match bytes.parse::<KdlDocument>() {
+        Ok(doc) => doc,
+        Err(error) => {
+            return ParsedFile {
+                records: vec![],
+                invalid: vec![InvalidRecord::unreadable(file, bytes, error.into())],
+            }
+        }
+    };
 
+    let mut parsed = ParsedFile::default();
     for node in doc.nodes() {
-        let Some(def) = definitions.get(node.name().value()) else {
-            return Err(miette::diagnostic!(
-                labels = vec![LabeledSpan::new_primary_with_span(None, node.name().span())],
-                "Unknown record kind"
-            ))?;
-        };
+        match parse_record_node(node, bytes, file, definitions, timezone) {
+            Ok(record) => parsed.records.push(record),
+            Err(errors) => parsed
+                .invalid
+                .push(InvalidRecord::new(node, bytes, file, timezone, errors)),
+        }
+    }
 
-        let Some(at_entry) = node.entry(0) else {
-            return Err(miette::diagnostic!(
-                labels = vec![LabeledSpan::new_primary_with_span(None, node.name().span())],
-                "Every record has to have a first argument with a datetime formatted as RFC3339."
-            ))?;
-        };
+    parsed
+}
+
+/// Parses a single record, returning all problems with it if it is not valid
+///
+/// Without a known kind or a datetime nothing else can be checked, otherwise all problems are
+/// found.
+fn parse_record_node(
+    node: &KdlNode,
+    bytes: &str,
+    file: &Utf8Path,
+    definitions: &BTreeMap<String, Vec<Definition>>,
+    timezone: &TimeZone,
+) -> Result<Record, Vec<miette::Report>> {
+    let Some(def) = definitions.get(node.name().value()) else {
+        return Err(vec![miette::diagnostic!(
+            labels = vec![LabeledSpan::new_primary_with_span(None, node.name().span())],
+            "Unknown record kind"
+        )
+        .into()]);
+    };
+
+    let Some(at_entry) = node.entry(0) else {
+        return Err(vec![miette::diagnostic!(
+            labels = vec![LabeledSpan::new_primary_with_span(None, node.name().span())],
+            "Every record has to have a first argument with a datetime formatted as RFC3339."
+        )
+        .into()]);
+    };
+
+    let Some((at, precision)) = at_entry
+        .value()
+        .as_string()
+        .and_then(|at| parse_timestamp(at, timezone).ok())
+    else {
+        return Err(vec![miette::diagnostic!(
+            labels = vec![LabeledSpan::new_primary_with_span(None, at_entry.span())],
+            "This datetime should be a string formatted as RFC3339."
+        )
+        .into()]);
+    };
+
+    let mut errors = vec![];
+
+    let until = parse_record_end(node, &at, timezone).unwrap_or_else(|e| {
+        errors.push(e);
+        None
+    });
 
-        let KdlValue::String(at) = at_entry.value() else {
-            return Err(miette::diagnostic!(
-                labels = vec![LabeledSpan::new_primary_with_span(None, at_entry.span())],
-                "This datetime should be a string formatted as RFC3339."
-            ))?;
+    let id = node
+        .entry("id")
+        .map(|id| match id.value() {
+            KdlValue::String(id) => Ok(id.clone()),
+            _ => Err(miette::diagnostic!(
+                labels = vec![LabeledSpan::new_primary_with_span(None, id.span())],
+                "The id of a record has to be a string."
+            )),
+        })
+        .transpose()
+        .unwrap_or_else(|e| {
+            errors.push(e.into());
+            None
+        });
+
+    let recurrence = node
+        .entry("repeat")
+        .map(|repeat| {
+            repeat
+                .value()
+                .as_string()
+                .ok_or_else(|| String::from("The rule has to be a string"))
+                .and_then(|rule| RecurrenceRule::parse(rule, timezone))
+                .map_err(|e| {
+                    miette::diagnostic!(
+                        labels = vec![LabeledSpan::new_primary_with_span(None, repeat.span())],
+                        help = e,
+                        "This is not a supported recurrence rule."
+                    )
+                })
+        })
+        .transpose()
+        .unwrap_or_else(|e| {
+            errors.push(e.into());
+            None
+        });
+
+    let matching_def = definition_at(def, at.timestamp());
+    let mut fields = BTreeMap::new();
+    for field in node.iter_children() {
+        let name = field.name();
+
+        let Some(value) = field.get(0) else {
+            errors.push(
+                miette::diagnostic!(
+                    labels = vec![LabeledSpan::new_primary_with_span(None, name.span())],
+                    "This field has no value."
+                )
+                .into(),
+            );
+            continue;
         };
 
-        let Ok(at) = parse_timestamp(at) else {
-            return Err(miette::diagnostic!(
-                labels = vec![LabeledSpan::new_primary_with_span(None, at_entry.span())],
-                "This datetime should be a string formatted as RFC3339."
-            ))?;
+        let Some(kind) = matching_def.fields.get(name.value()) else {
+            errors.push(
+                miette::diagnostic!(
+                    labels = vec![LabeledSpan::new_primary_with_span(
+                        Some(String::from("here")),
+                        name.span()
+                    )],
+                    help = format!(
+                        "Fields of \"{}\" are: {}",
+                        matching_def.name,
+                        matching_def
+                            .fields
+                            .keys()
+                            .cloned()
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ),
+                    "This field is not part of the definition."
+                )
+                .into(),
+            );
+            continue;
         };
 
-        let fields = node
-            .iter_children()
-            .map(|field| {
-                let Some(get) = field.get(0) else {
-                    return Err(miette::diagnostic!(
-                        labels = vec![LabeledSpan::new_primary_with_span(None, at_entry.span())],
-                        "This datetime should be a string formatted as RFC3339."
-                    ))?;
-                };
-                Ok::<_, miette::Report>((field.name().clone(), get.clone()))
-            })
-            .map(|val| match val {
-                Ok((name, val)) => {
-                    let matching_def =
-                        &def[def.partition_point(|v| v.since > at).saturating_sub(1)];
+        if let Err(e) = kind.validate(value) {
+            errors.push(
+                miette::diagnostic!(
+                    labels = vec![LabeledSpan::new_primary_with_span(
+                        Some(String::from("here")),
+                        name.span()
+                    )],
+                    help = e,
+                    "This field has the wrong kind."
+                )
+                .into(),
+            );
+            continue;
+        }
 
-                    let kind = &matching_def.fields[name.value()];
+        fields.insert(name.to_string(), value.clone());
+    }
 
-                    if let Err(e) = kind.validate(&val) {
-                        Err(miette::diagnostic!(
-                            labels = vec![LabeledSpan::new_primary_with_span(
-                                Some(String::from("here")),
-                                name.span()
-                            )],
-                            help = e,
-                            "This field has the wrong kind."
-                        ))?;
-                    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
 
-                    Ok((name.to_string(), val))
-                }
-                Err(err) => Err(err),
+    Ok(Record {
+        kind: node.name().to_string(),
+        id,
+        at,
+        precision,
+        until,
+        recurrence,
+        source: None,
+        location: Location::new(file, bytes, node.span()),
+        fields,
+    })
+}
+
+/// Finds all record files below `root`, sorted by path
+///
+/// Hidden files, the definitions folder and anything listed in a `.plaixtignore` are skipped.
+pub(crate) fn discover_record_files(
+    root: &Utf8Path,
+    definitions_folder: &Utf8Path,
+    globs: &RecordGlobs,
+) -> miette::Result<Vec<Utf8PathBuf>> {
+    fn glob_set(globs: &[String]) -> miette::Result<GlobSet> {
+        globs
+            .iter()
+            .try_fold(GlobSetBuilder::new(), |mut builder, glob| {
+                builder.add(Glob::new(glob)?);
+                Ok::<_, globset::Error>(builder)
             })
-            .collect::<Result<_, _>>()?;
+            .and_then(|builder| builder.build())
+            .into_diagnostic()
+    }
 
-        recs.push(Record {
-            kind: node.name().to_string(),
-            at,
-            fields,
-        });
+    let include = glob_set(&globs.include)?;
+    let exclude = glob_set(&globs.exclude)?;
+    let definitions = root.join(definitions_folder);
+
+    let mut files = vec![];
+
+    for entry in WalkBuilder::new(root)
+        .standard_filters(false)
+        .hidden(true)
+        .add_custom_ignore_filename(".plaixtignore")
+        .filter_entry(move |entry| entry.path() != definitions)
+        .build()
+    {
+        let entry = entry.into_diagnostic()?;
+        if !entry.file_type().is_some_and(|ty| ty.is_file()) {
+            continue;
+        }
+
+        let path = Utf8PathBuf::from_path_buf(entry.into_path())
+            .map_err(|path| miette::miette!("Path is not valid UTF-8: {}", path.display()))?;
+        let relative = path.strip_prefix(root).unwrap_or(&path);
+
+        if include.is_match(relative) && !exclude.is_match(relative) {
+            files.push(path);
+        }
     }
 
-    Ok(recs)
+    files.sort();
+
+    Ok(files)
 }
 
+/// Loads all records below `path`, in chronological order
+///
+/// When loading leniently, records that are not valid are returned separately instead of
+/// failing.
 pub(crate) async fn load_records(
     path: &Utf8Path,
+    definitions_folder: &Utf8Path,
+    globs: &RecordGlobs,
     definitions: &BTreeMap<String, Vec<Definition>>,
-) -> miette::Result<Vec<Record>> {
-    let defs = ReadDirStream::new(tokio::fs::read_dir(path).await.into_diagnostic()?)
-        .map_err(miette::Report::from_err)
-        .and_then(|entry| async move {
-            if entry.file_type().await.into_diagnostic()?.is_file() {
-                Ok(Some((
-                    Utf8PathBuf::from_path_buf(entry.path().to_path_buf()).unwrap(),
-                    tokio::fs::read_to_string(entry.path())
-                        .await
-                        .into_diagnostic()?,
-                )))
-            } else {
-                Ok(None)
+    timezone: &TimeZone,
+    options: &LoadOptions,
+) -> miette::Result<(Vec<Record>, Vec<InvalidRecord>)> {
+    let files = load_record_files(
+        path,
+        definitions_folder,
+        globs,
+        definitions,
+        timezone,
+        options,
+        BTreeMap::new(),
+    )
+    .await?;
+
+    collect_records(files.into_values(), options)
+}
+
+/// Loads the records of every record file below `path`, by file
+///
+/// Files in `unchanged` keep the records given for them, all other files are read and parsed.
+pub(crate) async fn load_record_files(
+    path: &Utf8Path,
+    definitions_folder: &Utf8Path,
+    globs: &RecordGlobs,
+    definitions: &BTreeMap<String, Vec<Definition>>,
+    timezone: &TimeZone,
+    options: &LoadOptions,
+    mut unchanged: BTreeMap<Utf8PathBuf, ParsedFile>,
+) -> miette::Result<BTreeMap<Utf8PathBuf, ParsedFile>> {
+    let files = {
+        let path = path.to_path_buf();
+        let definitions_folder = definitions_folder.to_path_buf();
+        let globs = globs.clone();
+        tokio::task::spawn_blocking(move || {
+            discover_record_files(&path, &definitions_folder, &globs)
+        })
+        .await
+        .into_diagnostic()??
+    };
+
+    let mut loaded = BTreeMap::new();
+    let mut changed = vec![];
+    for file in files {
+        match unchanged.remove(&file) {
+            Some(parsed) => {
+                loaded.insert(file, parsed);
+            }
+            None => changed.push(file),
+        }
+    }
+    // Entries of files that are kept are not looked at, so they would look unused
+    let prune = loaded.is_empty();
+
+    let cache = options
+        .cache
+        .then(|| Cache::open(path, definitions, timezone))
+        .flatten()
+        .map(Arc::new);
+    let parsed = {
+        let definitions = Arc::new(definitions.clone());
+        let timezone = timezone.clone();
+        let cache = cache.clone();
+        let lenient = options.lenient;
+        parse_files(changed.clone(), "Loading records", move |bytes, name| {
+            if let Some(records) = cache.as_ref().and_then(|cache| cache.get(name, bytes)) {
+                return Ok(ParsedFile {
+                    records,
+                    invalid: vec![],
+                });
             }
+
+            let parsed = match lenient {
+                true => parse_record_leniently(bytes, name, &definitions, &timezone),
+                false => ParsedFile {
+                    records: parse_record(bytes, name, &definitions, &timezone)?,
+                    invalid: vec![],
+                },
+            };
+            // Files with invalid records are about to be fixed, and have to fail when loading
+            // strictly
+            if let Some(cache) = cache.as_ref().filter(|_| parsed.invalid.is_empty()) {
+                cache.put(name, bytes, &parsed.records);
+            }
+            Ok(parsed)
         })
-        .flat_map(|val| futures::stream::iter(val.transpose()))
-        .and_then(|(name, bytes)| async move {
-            parse_record(&bytes, definitions)
-                .map_err(|e| e.with_source_code(NamedSource::new(name, bytes).with_language("kdl")))
+        .await?
+    };
+    loaded.extend(changed.into_iter().zip(parsed));
+
+    if let Some(cache) = cache.filter(|_| prune) {
+        tokio::task::spawn_blocking(move || cache.prune())
+            .await
+            .into_diagnostic()?;
+    }
+
+    Ok(loaded)
+}
+
+/// Puts the records of all files in chronological order, expanding recurring records into
+/// their occurrences inside of the window of `options`
+///
+/// When loading leniently, records reusing an id are kept as invalid records instead of
+/// failing.
+pub(crate) fn collect_records(
+    files: impl IntoIterator<Item = ParsedFile>,
+    options: &LoadOptions,
+) -> miette::Result<(Vec<Record>, Vec<InvalidRecord>)> {
+    let mut records = vec![];
+    let mut invalid = vec![];
+    for file in files {
+        records.extend(file.records);
+        invalid.extend(file.invalid);
+    }
+
+    if options.lenient {
+        let mut seen: BTreeMap<String, Location> = BTreeMap::new();
+        records.retain(|record| {
+            let Some(id) = record.id.as_deref() else {
+                return true;
+            };
+
+            match seen.get(id) {
+                None => {
+                    seen.insert(id.to_string(), record.location.clone());
+                    true
+                }
+                Some(first) => {
+                    invalid.push(InvalidRecord {
+                        kind: Some(record.kind.clone()),
+                        at: Some(record.at.clone()),
+                        fields: record.fields.clone(),
+                        errors: vec![format!(
+                            "The id \"{id}\" is not unique. It is already used by the record at {}:{}",
+                            first.file, first.line
+                        )],
+                        location: record.location.clone(),
+                    });
+                    false
+                }
+            }
+        });
+        invalid.sort_by(|a, b| {
+            (&a.location.file, a.location.line).cmp(&(&b.location.file, b.location.line))
+        });
+    } else {
+        check_unique_ids(&records)?;
+    }
+
+    let mut records = recurrence::expand(records, &options.window);
+    records.sort_by(Record::cmp_time);
+
+    Ok((records, invalid))
+}
+
+/// Reads and parses `files` concurrently, returning the results in the order of `files`
+///
+/// Parsing KDL is CPU-bound, so it happens on the blocking pool. At most a few files per CPU are
+/// read and parsed at once.
+async fn parse_files<T: Send + 'static>(
+    files: Vec<Utf8PathBuf>,
+    label: &'static str,
+    parse: impl Fn(&str, &Utf8Path) -> miette::Result<T> + Send + Sync + 'static,
+) -> miette::Result<Vec<T>> {
+    let parse = Arc::new(parse);
+    let mut progress = Progress::new(label, files.len());
+    let limit = std::thread::available_parallelism().map_or(4, NonZero::get) * 2;
+    let count = files.len();
+
+    let parsed = futures::stream::iter(files)
+        .map(|name| {
+            let parse = parse.clone();
+            async move {
+                let bytes = tokio::fs::read_to_string(&name).await.into_diagnostic()?;
+                tokio::task::spawn_blocking(move || {
+                    parse(&bytes, &name).map_err(|e| {
+                        e.with_source_code(NamedSource::new(name, bytes).with_language("kdl"))
+                    })
+                })
+                .await
+                .into_diagnostic()?
+            }
         })
-        .map(|val| val.map(|recs| futures::stream::iter(recs).map(Ok::<_, miette::Report>)))
-        .try_flatten()
+        // Unlike `buffer_unordered`, this keeps the order no matter which file is done first
+        .buffered(limit)
+        .inspect(|_| progress.advance())
         .try_collect()
         .await?;
 
-    Ok(defs)
+    debug!(count, "{label}: done");
+    Ok(parsed)
 }
 
-#[derive(Debug, Clone)]
+/// Makes sure that no two records share an id
+pub(crate) fn check_unique_ids(records: &[Record]) -> miette::Result<()> {
+    let mut seen: BTreeMap<&str, &Location> = BTreeMap::new();
+
+    for record in records {
+        let Some(id) = record.id.as_deref() else {
+            continue;
+        };
+
+        if let Some(first) = seen.insert(id, &record.location) {
+            return Err(record.location.report(miette::diagnostic!(
+                help = format!(
+                    "It is already used by the record at {}:{}",
+                    first.file, first.line
+                ),
+                "The id \"{id}\" is not unique."
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Hash)]
 pub enum DefinitionKind {
     String,
     Path,
     OneOf(Vec<String>),
+    /// The id of a document in the configured paperless instance
+    Paperless,
 }
 
 impl DefinitionKind {
@@ -165,6 +884,19 @@ impl DefinitionKind {
             DefinitionKind::String => String::from("String!"),
             DefinitionKind::Path => String::from("Path!"),
             DefinitionKind::OneOf(_vecs) => String::from("String!"),
+            // Missing if paperless does not know the document, or cannot be reached
+            DefinitionKind::Paperless => String::from("PaperlessDocument"),
+        }
+    }
+
+    /// The value of a field of this kind given as `value` on the command line
+    pub(crate) fn parse_value(&self, value: String) -> KdlValue {
+        match self {
+            DefinitionKind::Paperless => value
+                .parse()
+                .map(KdlValue::Integer)
+                .unwrap_or(KdlValue::String(value)),
+            _ => KdlValue::String(value),
         }
     }
 
@@ -183,6 +915,11 @@ impl DefinitionKind {
                 .is_some_and(|val| options.iter().any(|o| o == val))
                 .then_some(())
                 .ok_or_else(|| format!("Expected one of: {}", options.join(", "))),
+            DefinitionKind::Paperless => val
+                .as_integer()
+                .is_some_and(|id| u64::try_from(id).is_ok())
+                .then_some(())
+                .ok_or("Expected the id of a paperless document here".to_string()),
         }
     }
 
@@ -210,21 +947,41 @@ impl TryFrom<&str> for DefinitionKind {
         match value.to_ascii_lowercase().as_str() {
             "string" => Ok(DefinitionKind::String),
             "path" => Ok(DefinitionKind::Path),
+            "paperless" => Ok(DefinitionKind::Paperless),
             other => miette::bail!("Did not recognize valid field kind: \"{other}\""),
         }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Hash)]
 pub struct Definition {
     pub(crate) name: String,
     pub(crate) since: Timestamp,
     pub(crate) fields: BTreeMap<String, DefinitionKind>,
+    /// The names of `fields` in the order they were declared in
+    pub(crate) order: Vec<String>,
+    /// Values suggested for fields when adding new records
+    pub(crate) defaults: BTreeMap<String, KdlValue>,
+    /// What records of this kind are, from the `description` of the `define` or the comments
+    /// before it
+    pub(crate) description: Option<String>,
+    /// What the fields hold, from their `description` or the comments before or after them
+    pub(crate) field_descriptions: BTreeMap<String, String>,
+}
+
+/// The definition that was live at `at`
+///
+/// Records from before the first definition use the first definition.
+pub(crate) fn definition_at(definitions: &[Definition], at: Timestamp) -> &Definition {
+    &definitions[definitions
+        .partition_point(|v| v.since <= at)
+        .saturating_sub(1)]
 }
 
 pub(crate) fn parse_definition(
     bytes: &str,
     definition_name: String,
+    timezone: &TimeZone,
 ) -> miette::Result<Vec<Definition>> {
     let doc: KdlDocument = bytes.parse()?;
 
@@ -253,8 +1010,8 @@ pub(crate) fn parse_definition(
                     ))?;
                 };
 
-                let since = match parse_timestamp(since) {
-                    Ok(since) => since,
+                let since = match parse_timestamp(since, timezone) {
+                    Ok((since, _)) => since.timestamp(),
                     Err(_err) => {
                         return Err(miette::diagnostic!(
                             labels = vec![LabeledSpan::new_primary_with_span(
@@ -279,7 +1036,20 @@ pub(crate) fn parse_definition(
                     ))?;
                 };
 
-                let fields = fields
+                let field_descriptions = fields
+                    .iter_children()
+                    .map(|field| {
+                        let comments = field.format().and_then(|format| {
+                            comment_text(&format.leading)
+                                .or_else(|| comment_text(&format.terminator))
+                        });
+                        let description = description(field, comments)?;
+                        Ok(description.map(|desc| (field.name().value().to_string(), desc)))
+                    })
+                    .filter_map(Result::transpose)
+                    .collect::<miette::Result<_>>()?;
+
+                let fields: Vec<(String, DefinitionKind, Option<KdlValue>)> = fields
                     .iter_children()
                     .map(|field| {
                         let kind = if let Some(kind) = field.get("is") {
@@ -332,14 +1102,45 @@ pub(crate) fn parse_definition(
                             _ => {}
                         }
 
-                        Ok((field.name().to_string(), kind))
+                        let default = field
+                            .entry("default")
+                            .map(|default| {
+                                kind.validate(default.value()).map_err(|e| {
+                                    miette::diagnostic!(
+                                        labels = vec![LabeledSpan::new_primary_with_span(
+                                            Some(String::from("this default")),
+                                            default.span()
+                                        )],
+                                        help = e,
+                                        "The default does not fit the field."
+                                    )
+                                })?;
+                                Ok::<_, miette::Report>(default.value().clone())
+                            })
+                            .transpose()?;
+
+                        Ok((field.name().to_string(), kind, default))
                     })
                     .collect::<miette::Result<_>>()?;
 
                 defs.push(Definition {
                     since,
-                    fields,
+                    order: fields.iter().map(|(name, _, _)| name.clone()).collect(),
+                    defaults: fields
+                        .iter()
+                        .filter_map(|(name, _, default)| Some((name.clone(), default.clone()?)))
+                        .collect(),
+                    fields: fields
+                        .into_iter()
+                        .map(|(name, kind, _)| (name, kind))
+                        .collect(),
                     name: definition_name.clone(),
+                    description: description(
+                        node,
+                        node.format()
+                            .and_then(|format| comment_text(&format.leading)),
+                    )?,
+                    field_descriptions,
                 });
             }
             unknown => {
@@ -361,35 +1162,297 @@ pub(crate) fn parse_definition(
     Ok(defs)
 }
 
+/// The `description` property of a node, falling back to the comments around it
+fn description(node: &KdlNode, comments: Option<String>) -> miette::Result<Option<String>> {
+    let Some(entry) = node.entry("description") else {
+        return Ok(comments);
+    };
+
+    match entry.value() {
+        KdlValue::String(description) => Ok(Some(description.clone())),
+        _ => Err(miette::diagnostic!(
+            labels = vec![LabeledSpan::new_primary_with_span(
+                Some(String::from("this description")),
+                entry.span()
+            )],
+            "The `description` property needs to be a string."
+        ))?,
+    }
+}
+
+/// The text of the comments in the formatting around a node, without their markers
+fn comment_text(formatting: &str) -> Option<String> {
+    let mut lines = vec![];
+    let mut in_block = false;
+
+    for line in formatting.lines() {
+        let mut line = line.trim();
+
+        if !in_block {
+            if let Some(comment) = line.strip_prefix("//") {
+                lines.push(comment.trim());
+                continue;
+            }
+
+            match line.strip_prefix("/*") {
+                Some(comment) => {
+                    in_block = true;
+                    line = comment;
+                }
+                None => continue,
+            }
+        }
+
+        if let Some((comment, _)) = line.split_once("*/") {
+            in_block = false;
+            line = comment;
+        }
+
+        let line = line.trim().trim_start_matches('*').trim();
+        if !line.is_empty() {
+            lines.push(line);
+        }
+    }
+
+    (!lines.is_empty()).then(|| lines.join("\n"))
+}
+
 pub(crate) async fn load_definitions(
     path: &Utf8Path,
+    timezone: &TimeZone,
 ) -> miette::Result<BTreeMap<String, Vec<Definition>>> {
-    let defs = ReadDirStream::new(tokio::fs::read_dir(path).await.into_diagnostic()?)
-        .map_err(miette::Report::from_err)
-        .and_then(|entry| async move {
-            if entry.file_type().await.into_diagnostic()?.is_file() {
-                Ok(Some((
-                    Utf8PathBuf::from_path_buf(entry.path().to_path_buf()).unwrap(),
-                    tokio::fs::read_to_string(entry.path())
-                        .await
-                        .into_diagnostic()?,
-                )))
-            } else {
-                Ok(None)
-            }
-        })
-        .flat_map(|val| futures::stream::iter(val.transpose()))
-        .and_then(|(name, bytes)| async move {
-            let definition_name = name.file_stem().unwrap().to_string();
-            Ok((
-                definition_name.clone(),
-                parse_definition(&bytes, definition_name).map_err(|e| {
-                    e.with_source_code(NamedSource::new(name, bytes).with_language("kdl"))
-                })?,
-            ))
+    let mut files: Vec<Utf8PathBuf> =
+        ReadDirStream::new(tokio::fs::read_dir(path).await.into_diagnostic()?)
+            .map_err(miette::Report::from_err)
+            .and_then(|entry| async move {
+                let hidden = entry.file_name().to_string_lossy().starts_with('.');
+                if entry.file_type().await.into_diagnostic()?.is_file() && !hidden {
+                    Ok(Some(
+                        Utf8PathBuf::from_path_buf(entry.path().to_path_buf()).unwrap(),
+                    ))
+                } else {
+                    Ok(None)
+                }
+            })
+            .flat_map(|val| futures::stream::iter(val.transpose()))
+            .try_collect()
+            .await?;
+    files.sort();
+
+    let timezone = timezone.clone();
+    let defs = parse_files(files, "Loading definitions", move |bytes, name| {
+        let definition_name = name.file_stem().unwrap().to_string();
+        Ok((
+            definition_name.clone(),
+            parse_definition(bytes, definition_name, &timezone)?,
+        ))
+    })
+    .await?;
+
+    Ok(defs.into_iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use jiff::tz::TimeZone;
+
+    use super::definition_at;
+    use super::discover_record_files;
+    use super::parse_definition;
+    use super::parse_files;
+    use super::parse_record;
+    use super::parse_record_leniently;
+    use super::parse_timestamp;
+    use super::Precision;
+
+    #[test]
+    fn record_files_are_found_below_the_root() {
+        let root = tempfile::tempdir().unwrap();
+        let root = camino::Utf8Path::from_path(root.path()).unwrap();
+        for file in [
+            "a.plrecs",
+            "notes.txt",
+            "nested/deep/b.plrecs",
+            "extra/c.kdl",
+            "archive/d.plrecs",
+            "ignored/e.plrecs",
+            "definitions/f.plrecs",
+            ".hidden/g.plrecs",
+            ".h.plrecs",
+        ] {
+            let path = root.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+        std::fs::write(root.join(".plaixtignore"), "ignored/\n").unwrap();
+
+        let globs = crate::config::RecordGlobs {
+            include: vec![String::from("**/*.plrecs"), String::from("extra/*.kdl")],
+            exclude: vec![String::from("archive/**")],
+        };
+        let files = discover_record_files(root, "definitions".into(), &globs).unwrap();
+
+        assert_eq!(
+            files
+                .iter()
+                .map(|file| file.strip_prefix(root).unwrap().as_str())
+                .collect::<Vec<_>>(),
+            ["a.plrecs", "extra/c.kdl", "nested/deep/b.plrecs"]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn files_are_parsed_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = (0..100)
+            .map(|idx| {
+                let path = dir.path().join(format!("{idx}.plrecs"));
+                std::fs::write(&path, idx.to_string()).unwrap();
+                camino::Utf8PathBuf::from_path_buf(path).unwrap()
+            })
+            .collect::<Vec<_>>();
+
+        let parsed = parse_files(files, "Parsing", |bytes, _| {
+            let idx = bytes.parse::<u64>().unwrap();
+            // Earlier files take longer, so that they are done last
+            std::thread::sleep(std::time::Duration::from_micros(100 * (100 - idx)));
+            Ok(idx)
         })
-        .try_collect()
-        .await?;
+        .await
+        .unwrap();
 
-    Ok(defs)
+        assert_eq!(parsed, (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn records_follow_the_definition_live_at_their_datetime() {
+        let definitions: std::collections::BTreeMap<_, _> = [(
+            String::from("purchase"),
+            parse_definition(
+                "define since=\"2024-01-01\" {\n\tfields {\n\t\tname is=string\n\t}\n}\ndefine since=\"2025-01-01\" {\n\tfields {\n\t\tname is=string\n\t\tprice is=string\n\t}\n}\n",
+                String::from("purchase"),
+                &TimeZone::UTC,
+            )
+            .unwrap(),
+        )]
+        .into();
+        let since = |at: &str| {
+            let at = at.parse::<jiff::Timestamp>().unwrap();
+            definition_at(&definitions["purchase"], at)
+                .since
+                .to_string()
+        };
+        assert_eq!(since("2023-06-01T00:00:00Z"), "2024-01-01T00:00:00Z");
+        assert_eq!(since("2024-06-01T00:00:00Z"), "2024-01-01T00:00:00Z");
+        assert_eq!(since("2025-01-01T00:00:00Z"), "2025-01-01T00:00:00Z");
+        assert_eq!(since("2025-06-01T00:00:00Z"), "2025-01-01T00:00:00Z");
+
+        let records = parse_record(
+            "purchase \"2025-06-01\" {\n\tprice \"2.50\"\n}\n",
+            "records.plrecs".into(),
+            &definitions,
+            &TimeZone::UTC,
+        )
+        .unwrap();
+        assert_eq!(records[0].fields["price"].as_string(), Some("2.50"));
+    }
+
+    #[test]
+    fn invalid_records_are_kept_with_all_their_problems() {
+        let definitions = [(
+            String::from("purchase"),
+            parse_definition(
+                "define since=\"2024-01-01\" {\n\tfields {\n\t\tname is=string\n\t\tprice is=string\n\t}\n}\n",
+                String::from("purchase"),
+                &TimeZone::UTC,
+            )
+            .unwrap(),
+        )]
+        .into();
+        let file = camino::Utf8Path::new("2019.plrecs");
+
+        let parsed = parse_record_leniently(
+            "purchase \"2019-03-01\" {\n\tname \"Bread\"\n\tprice \"2.50\"\n}\npurchase \"2019-03-02\" {\n\tname 12\n\tstore \"Corner\"\n}\nsale \"2019-03-03\"\n",
+            file,
+            &definitions,
+            &TimeZone::UTC,
+        );
+        assert_eq!(parsed.records.len(), 1);
+        assert_eq!(parsed.invalid.len(), 2);
+
+        let purchase = &parsed.invalid[0];
+        assert_eq!(purchase.kind.as_deref(), Some("purchase"));
+        assert_eq!(
+            purchase.at.as_ref().unwrap().date().to_string(),
+            "2019-03-02"
+        );
+        assert_eq!(purchase.location.line, 5);
+        assert_eq!(purchase.fields.len(), 2);
+        assert_eq!(purchase.errors.len(), 2, "{:?}", purchase.errors);
+
+        let sale = &parsed.invalid[1];
+        assert_eq!(sale.kind.as_deref(), Some("sale"));
+        assert_eq!(sale.errors.len(), 1);
+
+        let unreadable = parse_record_leniently("purchase {", file, &definitions, &TimeZone::UTC);
+        assert!(unreadable.records.is_empty());
+        assert_eq!(unreadable.invalid[0].kind, None);
+        assert!(!unreadable.invalid[0].errors.is_empty());
+    }
+
+    #[test]
+    fn comments_describe_definitions() {
+        let definitions = parse_definition(
+            "// Things bought\n/* in a store */\ndefine since=\"2024-01-01\" {\n\tfields {\n\t\t// What was bought\n\t\tname is=string\n\t\tstore is=string // Where\n\t\tprice is=string\n\t\t// Ignored\n\t\tcount is=string description=\"How many\"\n\t}\n}\n",
+            String::from("purchase"),
+            &TimeZone::UTC,
+        )
+        .unwrap();
+
+        let purchase = &definitions[0];
+        assert_eq!(
+            purchase.description.as_deref(),
+            Some("Things bought\nin a store")
+        );
+        assert_eq!(purchase.field_descriptions["name"], "What was bought");
+        assert_eq!(purchase.field_descriptions["store"], "Where");
+        assert!(!purchase.field_descriptions.contains_key("price"));
+        assert_eq!(purchase.field_descriptions["count"], "How many");
+    }
+
+    #[test]
+    fn timestamps_keep_their_zone() {
+        let berlin = TimeZone::get("Europe/Berlin").unwrap();
+
+        let (date, precision) = parse_timestamp("2025-02-01", &berlin).unwrap();
+        assert_eq!(precision, Precision::Day);
+        assert_eq!(date.to_string(), "2025-02-01T00:00:00+01:00[Europe/Berlin]");
+        assert_eq!(date.timestamp().to_string(), "2025-01-31T23:00:00Z");
+
+        let (offset, _) = parse_timestamp("2025-01-30 09:10:59-05:00", &berlin).unwrap();
+        assert_eq!(offset.offset().to_string(), "-05");
+        assert_eq!(offset.datetime().to_string(), "2025-01-30T09:10:59");
+
+        let (zoned, precision) =
+            parse_timestamp("2025-07-01T12:00[America/New_York]", &berlin).unwrap();
+        assert_eq!(zoned.time_zone().iana_name(), Some("America/New_York"));
+        assert_eq!(precision, Precision::Time);
+    }
+
+    #[test]
+    fn partial_dates_cover_their_period() {
+        let (month, precision) = parse_timestamp("2019-06", &TimeZone::UTC).unwrap();
+        assert_eq!(precision, Precision::Month);
+        assert_eq!(month.date().to_string(), "2019-06-01");
+        assert_eq!(
+            precision.end_of(&month).unwrap().date().to_string(),
+            "2019-07-01"
+        );
+
+        let (year, precision) = parse_timestamp("2019", &TimeZone::UTC).unwrap();
+        assert_eq!(precision, Precision::Year);
+        assert_eq!(year.date().to_string(), "2019-01-01");
+
+        assert!(parse_timestamp("2019-13", &TimeZone::UTC).is_err());
+    }
 }
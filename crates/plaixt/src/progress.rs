@@ -0,0 +1,55 @@
+use std::io::IsTerminal;
+use std::time::Duration;
+use std::time::Instant;
+
+/// Loading fewer files than this is quick enough to not need a progress line
+const MIN_FILES: usize = 200;
+
+/// How often the progress line is redrawn at most
+const INTERVAL: Duration = Duration::from_millis(100);
+
+/// Shows how many of a number of files have been loaded, on a single line of stderr
+///
+/// Nothing is shown for few files or when stderr is not a terminal. The line is cleared again
+/// once the progress is dropped.
+pub(crate) struct Progress {
+    label: &'static str,
+    done: usize,
+    total: usize,
+    enabled: bool,
+    shown: bool,
+    last: Instant,
+}
+
+impl Progress {
+    pub(crate) fn new(label: &'static str, total: usize) -> Progress {
+        Progress {
+            label,
+            done: 0,
+            total,
+            enabled: total >= MIN_FILES && std::io::stderr().is_terminal(),
+            shown: false,
+            last: Instant::now(),
+        }
+    }
+
+    /// Counts one more file as loaded
+    pub(crate) fn advance(&mut self) {
+        self.done += 1;
+
+        if self.enabled && self.last.elapsed() >= INTERVAL {
+            self.last = Instant::now();
+            self.shown = true;
+            eprint!("\r{} {}/{} files", self.label, self.done, self.total);
+        }
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        if self.shown {
+            // Clears the line, so that whatever comes next starts on a clean one
+            eprint!("\r\x1b[2K");
+        }
+    }
+}
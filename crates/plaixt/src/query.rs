@@ -0,0 +1,423 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use async_graphql_parser::Pos;
+use jiff::tz::TimeZone;
+use miette::LabeledSpan;
+use miette::MietteDiagnostic;
+use miette::NamedSource;
+use miette::SourceSpan;
+use trustfall::FieldValue;
+use trustfall_core::frontend::error::FrontendError;
+use trustfall_core::frontend::error::ValidationError;
+use trustfall_core::graphql_query::error::ParseError;
+use trustfall_core::ir::Argument;
+use trustfall_core::ir::IRQueryComponent;
+use trustfall_core::ir::IndexedQuery;
+use trustfall_core::ir::Operation;
+use trustfall_core::ir::Type;
+
+use crate::adapter::Adapter;
+use crate::parsing::parse_timestamp;
+
+/// Properties of records holding timestamps, variables compared to them are read as datetimes
+const TIMESTAMP_PROPERTIES: &[&str] = &["_at", "_start", "_end"];
+
+/// A query that was checked against the schema
+pub struct Query {
+    pub(crate) name: String,
+    pub(crate) text: String,
+    pub(crate) parsed: Arc<IndexedQuery>,
+}
+
+impl Query {
+    /// Parses `text`, pointing at the offending part of it if it is not a valid query
+    ///
+    /// Datetime parameters are read in `timezone`, like the adapter reads them.
+    pub(crate) fn parse(
+        schema: &trustfall::Schema,
+        name: impl Into<String>,
+        text: impl Into<String>,
+        timezone: &TimeZone,
+    ) -> miette::Result<Query> {
+        let name = name.into();
+        let text = text.into();
+
+        let parsed = trustfall_core::frontend::parse(schema, &text)
+            .map_err(|error| query_error(&text, error))
+            .and_then(|parsed| {
+                check_parameters(&text, &parsed, timezone)?;
+                Ok(parsed)
+            });
+
+        match parsed {
+            Ok(parsed) => Ok(Query { name, text, parsed }),
+            Err(error) => {
+                Err(error.with_source_code(NamedSource::new(name, text).with_language("graphql")))
+            }
+        }
+    }
+
+    /// The outputs of this query, in the order they are written in
+    pub(crate) fn columns(&self) -> Vec<Arc<str>> {
+        crate::output::output_order(&self.text, self.parsed.outputs.keys().cloned())
+    }
+
+    /// Converts `name=value` pairs given on the command line to the types of the variables
+    /// declared by this query
+    ///
+    /// Lists are separated by commas. Variables compared to `_at`, `_start` or `_end` may be
+    /// given as any datetime plaixt understands.
+    pub(crate) fn variables(
+        &self,
+        given: Vec<(String, String)>,
+        timezone: &TimeZone,
+    ) -> miette::Result<BTreeMap<Arc<str>, FieldValue>> {
+        let declared = &self.parsed.ir_query.variables;
+        let declared_help = || {
+            let declared = declared
+                .iter()
+                .map(|(name, ty)| format!("${name}: {ty}"))
+                .collect::<Vec<_>>();
+            match declared.is_empty() {
+                true => String::from("This query uses no variables"),
+                false => format!("This query uses: {}", declared.join(", ")),
+            }
+        };
+
+        let mut timestamps = vec![];
+        timestamp_variables(&self.parsed.ir_query.root_component, &mut timestamps);
+
+        let mut variables = BTreeMap::new();
+        for (name, value) in given {
+            let Some(ty) = declared.get(name.as_str()) else {
+                miette::bail!(help = declared_help(), "Unknown variable \"{name}\"");
+            };
+
+            let is_timestamp = timestamps.iter().any(|ts| ts.as_ref() == name);
+            let value = convert(&value, ty, is_timestamp, timezone).map_err(|e| {
+                miette::miette!(help = e, "The variable \"{name}\" is not a valid {ty}")
+            })?;
+
+            variables.insert(Arc::from(name), value);
+        }
+
+        let missing = declared
+            .keys()
+            .filter(|name| !variables.contains_key(*name))
+            .map(|name| format!("${name}"))
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            miette::bail!(
+                help = "Pass them with `--var name=value`",
+                "Missing values for {}",
+                missing.join(", ")
+            );
+        }
+
+        Ok(variables)
+    }
+
+    /// Runs this query, returning its rows
+    pub(crate) fn execute(
+        &self,
+        adapter: Arc<Adapter>,
+        variables: BTreeMap<Arc<str>, FieldValue>,
+    ) -> miette::Result<Box<dyn Iterator<Item = BTreeMap<Arc<str>, FieldValue>>>> {
+        trustfall_core::interpreter::execution::interpret_ir(
+            adapter,
+            self.parsed.clone(),
+            Arc::new(variables),
+        )
+        .map_err(|e| miette::miette!("{e}"))
+    }
+}
+
+/// The leading `#` comments of a query file, which describe it
+pub(crate) fn header(text: &str) -> Vec<&str> {
+    text.lines()
+        .map_while(|line| line.trim().strip_prefix('#'))
+        .map(str::trim)
+        .collect()
+}
+
+/// Collects variables that are compared against timestamp properties
+fn timestamp_variables(component: &IRQueryComponent, names: &mut Vec<Arc<str>>) {
+    for vertex in component.vertices.values() {
+        for filter in &vertex.filters {
+            let (field, argument) = match filter {
+                Operation::Equals(field, argument)
+                | Operation::NotEquals(field, argument)
+                | Operation::LessThan(field, argument)
+                | Operation::LessThanOrEqual(field, argument)
+                | Operation::GreaterThan(field, argument)
+                | Operation::GreaterThanOrEqual(field, argument)
+                | Operation::OneOf(field, argument)
+                | Operation::NotOneOf(field, argument) => (field, argument),
+                _ => continue,
+            };
+
+            if let Argument::Variable(variable) = argument {
+                if TIMESTAMP_PROPERTIES.contains(&field.field_name.as_ref()) {
+                    names.push(variable.variable_name.clone());
+                }
+            }
+        }
+    }
+
+    for fold in component.folds.values() {
+        timestamp_variables(&fold.component, names);
+    }
+}
+
+fn convert(
+    value: &str,
+    ty: &Type,
+    is_timestamp: bool,
+    timezone: &TimeZone,
+) -> Result<FieldValue, String> {
+    if value == "null" && ty.nullable() {
+        return Ok(FieldValue::Null);
+    }
+
+    if let Some(inner) = ty.as_list() {
+        let value = value.trim();
+        let value = value
+            .strip_prefix('[')
+            .and_then(|value| value.strip_suffix(']'))
+            .unwrap_or(value);
+
+        return value
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(|item| convert(item, &inner, is_timestamp, timezone))
+            .collect::<Result<Vec<_>, _>>()
+            .map(|items| FieldValue::List(items.into()));
+    }
+
+    match ty.base_type() {
+        "Int" => value
+            .parse::<i64>()
+            .map(FieldValue::Int64)
+            .map_err(|e| e.to_string()),
+        "Float" => value
+            .parse::<f64>()
+            .map(FieldValue::Float64)
+            .map_err(|e| e.to_string()),
+        "Boolean" => value
+            .parse::<bool>()
+            .map(FieldValue::Boolean)
+            .map_err(|_| String::from("Expected `true` or `false`")),
+        _ if is_timestamp => parse_timestamp(value, timezone)
+            .map(|(at, _)| at.timestamp().to_string().into())
+            .map_err(|e| format!("{e}")),
+        "String" | "ID" => Ok(value.into()),
+        other => Err(format!("Variables of type {other} are not supported")),
+    }
+}
+
+/// Checks that the `from` and `until` parameters of the entrypoint are datetimes
+///
+/// The adapter relies on this, as it cannot report errors while resolving the entrypoint.
+fn check_parameters(text: &str, parsed: &IndexedQuery, timezone: &TimeZone) -> miette::Result<()> {
+    for name in ["from", "until"] {
+        let Some(FieldValue::String(value)) = parsed.ir_query.root_parameters.get(name) else {
+            continue;
+        };
+
+        if let Err(error) = parse_timestamp(value, timezone) {
+            let labels = text
+                .find(&format!("\"{value}\""))
+                .map(|offset| LabeledSpan::new_primary_with_span(None, (offset, value.len() + 2)))
+                .or_else(|| {
+                    find_identifier(text, name)
+                        .map(|span| LabeledSpan::new_primary_with_span(None, span))
+                });
+
+            return Err(MietteDiagnostic::new(format!(
+                "The `{name}` parameter \"{value}\" is not a datetime"
+            ))
+            .with_labels(labels)
+            .with_help(format!("{error}"))
+            .into());
+        }
+    }
+
+    Ok(())
+}
+
+/// Turns the errors of a query into one diagnostic, with a label for every error that can be
+/// found in the query text
+fn query_error(text: &str, error: FrontendError) -> miette::Report {
+    let errors = match error {
+        FrontendError::MultipleErrors(errors) => errors.0,
+        error => vec![error],
+    };
+
+    let mut labels = vec![];
+    let mut unlocated = vec![];
+    for error in &errors {
+        match error_span(text, error) {
+            Some(span) if errors.len() == 1 => {
+                labels.push(LabeledSpan::new_primary_with_span(None, span))
+            }
+            Some(span) => labels.push(LabeledSpan::new_with_span(Some(error.to_string()), span)),
+            None if errors.len() == 1 => {}
+            None => unlocated.push(error.to_string()),
+        }
+    }
+
+    let message = match errors.as_slice() {
+        [error] => error.to_string(),
+        errors => format!("Found {} problems in this query", errors.len()),
+    };
+
+    let mut diagnostic = MietteDiagnostic::new(message).with_labels(labels);
+    if !unlocated.is_empty() {
+        diagnostic = diagnostic.with_help(unlocated.join("\n"));
+    }
+
+    diagnostic.into()
+}
+
+/// Where in the query text an error comes from
+///
+/// Parse errors know their position, for other errors the names they mention are looked up.
+fn error_span(text: &str, error: &FrontendError) -> Option<SourceSpan> {
+    let pos = match error {
+        FrontendError::ParseError(ParseError::InvalidGraphQL(error)) => error.positions().next(),
+        FrontendError::ParseError(error) => serde_json::to_value(error)
+            .ok()
+            .and_then(|value| find_pos(&value)),
+        _ => None,
+    };
+
+    if let Some(pos) = pos {
+        let offset = offset_of(text, pos)?;
+        let length = text[offset..]
+            .find(|c: char| !(c.is_alphanumeric() || matches!(c, '_' | '@' | '$')))
+            .unwrap_or(text.len() - offset)
+            .max(1);
+        return Some(SourceSpan::new(offset.into(), length));
+    }
+
+    let names = match error {
+        FrontendError::ValidationError(ValidationError::NonExistentPath(path)) => {
+            path.last().cloned().into_iter().collect()
+        }
+        error => error
+            .to_string()
+            .split('"')
+            .skip(1)
+            .step_by(2)
+            .map(str::to_string)
+            .collect::<Vec<_>>(),
+    };
+
+    names.iter().rev().find_map(|name| {
+        find_identifier(text, &format!("${name}")).or_else(|| find_identifier(text, name))
+    })
+}
+
+/// Finds a `{"line": .., "column": ..}` position in a serialized error
+fn find_pos(value: &serde_json::Value) -> Option<Pos> {
+    match value {
+        serde_json::Value::Object(map) if map.len() == 2 => serde_json::from_value(value.clone())
+            .ok()
+            .or_else(|| map.values().find_map(find_pos)),
+        serde_json::Value::Object(map) => map.values().find_map(find_pos),
+        serde_json::Value::Array(values) => values.iter().find_map(find_pos),
+        _ => None,
+    }
+}
+
+fn offset_of(text: &str, pos: Pos) -> Option<usize> {
+    let line_start = match pos.line {
+        0 | 1 => 0,
+        line => text.match_indices('\n').nth(line - 2)?.0 + 1,
+    };
+    let line = &text[line_start..];
+
+    Some(
+        line_start
+            + line
+                .char_indices()
+                .nth(pos.column.saturating_sub(1))
+                .map_or(line.len(), |(idx, _)| idx),
+    )
+}
+
+/// The first occurrence of `name` in `text` that is not part of a longer name
+fn find_identifier(text: &str, name: &str) -> Option<SourceSpan> {
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+
+    text.match_indices(name)
+        .find(|(idx, _)| {
+            let before = text[..*idx].chars().next_back();
+            let after = text[idx + name.len()..].chars().next();
+            !before.is_some_and(is_ident) && !after.is_some_and(is_ident)
+        })
+        .map(|(idx, _)| SourceSpan::new(idx.into(), name.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use async_graphql_parser::Pos;
+    use jiff::tz::TimeZone;
+    use trustfall::FieldValue;
+    use trustfall_core::ir::Type;
+
+    use super::convert;
+    use super::find_identifier;
+    use super::offset_of;
+    use super::Query;
+    use crate::adapter::Adapter;
+
+    #[test]
+    fn variables_are_typed() {
+        let ints = Type::parse("[Int!]").unwrap();
+        assert_eq!(
+            convert("[1, 2]", &ints, false, &TimeZone::UTC),
+            Ok(FieldValue::List([1i64.into(), 2i64.into()].into()))
+        );
+        assert_eq!(
+            convert("null", &ints, false, &TimeZone::UTC),
+            Ok(FieldValue::Null)
+        );
+        assert!(convert("one", &ints, false, &TimeZone::UTC).is_err());
+
+        let string = Type::parse("String!").unwrap();
+        assert_eq!(
+            convert("2024-10-30", &string, true, &TimeZone::UTC),
+            Ok(FieldValue::from("2024-10-30T00:00:00Z"))
+        );
+
+        let query = "{\n  Records { titl @output }\n}";
+        let offset = offset_of(
+            query,
+            Pos {
+                line: 2,
+                column: 13,
+            },
+        )
+        .unwrap();
+        assert_eq!(&query[offset..offset + 4], "titl");
+        assert_eq!(find_identifier(query, "titl").unwrap().offset(), offset);
+    }
+
+    #[test]
+    fn parameters_are_checked_in_the_configured_timezone() {
+        let schema = Adapter::schema();
+        let query = r#"{ Records(until: "9999-12-30T20:00:00") { _at @output } }"#;
+
+        assert!(Query::parse(schema, "query", query, &TimeZone::UTC).is_ok());
+        assert!(Query::parse(
+            schema,
+            "query",
+            query,
+            &TimeZone::get("America/New_York").unwrap()
+        )
+        .is_err());
+    }
+}
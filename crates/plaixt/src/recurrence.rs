@@ -0,0 +1,298 @@
+use std::sync::Arc;
+
+use jiff::fmt::strtime;
+use jiff::tz::TimeZone;
+use jiff::Span;
+use jiff::Timestamp;
+use jiff::Unit;
+use jiff::Zoned;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::parsing::Record;
+
+/// The supported subset of RFC 5545 recurrence rules
+///
+/// Only `FREQ`, `INTERVAL`, `COUNT` and `UNTIL` are understood. Like in RFC 5545, occurrences
+/// that would fall on a day that does not exist (e.g. the 31st of a shorter month) are skipped.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecurrenceRule {
+    pub(crate) frequency: Frequency,
+    pub(crate) interval: i64,
+    pub(crate) count: Option<usize>,
+    pub(crate) until: Option<Timestamp>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+impl RecurrenceRule {
+    pub(crate) fn parse(rule: &str, timezone: &TimeZone) -> Result<RecurrenceRule, String> {
+        let rule = rule.strip_prefix("RRULE:").unwrap_or(rule);
+
+        let mut frequency = None;
+        let mut interval = 1;
+        let mut count = None;
+        let mut until = None;
+
+        for part in rule.split(';').filter(|part| !part.is_empty()) {
+            let Some((key, value)) = part.split_once('=') else {
+                return Err(format!("Expected `KEY=VALUE`, got \"{part}\""));
+            };
+
+            match key.to_ascii_uppercase().as_str() {
+                "FREQ" => {
+                    frequency = Some(match value.to_ascii_uppercase().as_str() {
+                        "DAILY" => Frequency::Daily,
+                        "WEEKLY" => Frequency::Weekly,
+                        "MONTHLY" => Frequency::Monthly,
+                        "YEARLY" => Frequency::Yearly,
+                        other => {
+                            return Err(format!(
+                                "Unsupported frequency \"{other}\", expected one of: DAILY, WEEKLY, MONTHLY, YEARLY"
+                            ))
+                        }
+                    })
+                }
+                "INTERVAL" => {
+                    interval = value
+                        .parse()
+                        .ok()
+                        .filter(|interval| *interval > 0)
+                        .ok_or_else(|| format!("INTERVAL must be a positive number, got \"{value}\""))?
+                }
+                "COUNT" => {
+                    count = Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("COUNT must be a number, got \"{value}\""))?,
+                    )
+                }
+                "UNTIL" => until = Some(parse_until(value, timezone)?),
+                other => {
+                    return Err(format!(
+                        "Unsupported rule part \"{other}\", expected one of: FREQ, INTERVAL, COUNT, UNTIL"
+                    ))
+                }
+            }
+        }
+
+        if count.is_some() && until.is_some() {
+            return Err(String::from("COUNT and UNTIL cannot be used together"));
+        }
+
+        Ok(RecurrenceRule {
+            frequency: frequency.ok_or("A recurrence rule needs a FREQ")?,
+            interval,
+            count,
+            until,
+        })
+    }
+
+    fn step(&self, n: i64) -> Span {
+        let n = n * self.interval;
+        match self.frequency {
+            Frequency::Daily => Span::new().days(n),
+            Frequency::Weekly => Span::new().weeks(n),
+            Frequency::Monthly => Span::new().months(n),
+            Frequency::Yearly => Span::new().years(n),
+        }
+    }
+
+    /// All starts of this rule beginning at `start`, up to and including `end`
+    pub(crate) fn occurrences<'a>(
+        &'a self,
+        start: &'a Zoned,
+        end: Timestamp,
+    ) -> impl Iterator<Item = Zoned> + 'a {
+        let end = self.until.map_or(end, |until| until.min(end));
+
+        (0..)
+            .map(move |n| start.checked_add(self.step(n)).ok())
+            .take_while(move |at| at.as_ref().is_some_and(|at| at.timestamp() <= end))
+            .flatten()
+            .filter(move |at| match self.frequency {
+                Frequency::Monthly | Frequency::Yearly => at.day() == start.day(),
+                Frequency::Daily | Frequency::Weekly => true,
+            })
+            .take(self.count.unwrap_or(usize::MAX))
+    }
+}
+
+/// `UNTIL` may be given in the RFC 5545 basic format, or like any other plaixt datetime
+fn parse_until(value: &str, timezone: &TimeZone) -> Result<Timestamp, String> {
+    let basic = if value.len() == 8 {
+        strtime::parse("%Y%m%d", value)
+            .and_then(|parsed| parsed.to_date())
+            .and_then(|date| date.to_zoned(timezone.clone()))
+            .map(|zoned| zoned.timestamp())
+    } else if let Some(value) = value.strip_suffix('Z') {
+        strtime::parse("%Y%m%dT%H%M%S", value)
+            .and_then(|parsed| parsed.to_datetime())
+            .and_then(|datetime| datetime.to_zoned(TimeZone::UTC))
+            .map(|zoned| zoned.timestamp())
+    } else {
+        strtime::parse("%Y%m%dT%H%M%S", value)
+            .and_then(|parsed| parsed.to_datetime())
+            .and_then(|datetime| datetime.to_zoned(timezone.clone()))
+            .map(|zoned| zoned.timestamp())
+    };
+
+    basic
+        .or_else(|_| crate::parsing::parse_timestamp(value, timezone).map(|(at, _)| at.timestamp()))
+        .map_err(|_| format!("Could not parse UNTIL \"{value}\" as a datetime"))
+}
+
+/// The span of time recurring records are expanded in
+#[derive(Debug, Clone)]
+pub struct Window {
+    pub(crate) from: Option<Timestamp>,
+    pub(crate) until: Timestamp,
+}
+
+impl Default for Window {
+    /// Everything that has happened up to now
+    fn default() -> Self {
+        Window {
+            from: None,
+            until: Timestamp::now(),
+        }
+    }
+}
+
+/// Replaces every recurring record with its occurrences inside of `window`
+///
+/// Each occurrence keeps the duration of its source, and links back to it. The written
+/// occurrence is always kept, even when it lies after the window, so that records planned
+/// ahead do not disappear.
+pub(crate) fn expand(records: Vec<Record>, window: &Window) -> Vec<Record> {
+    let mut expanded = Vec::with_capacity(records.len());
+
+    for record in records {
+        let Some(rule) = record.recurrence.clone() else {
+            expanded.push(record);
+            continue;
+        };
+
+        let source = Arc::new(record);
+        let length = source
+            .until
+            .as_ref()
+            .and_then(|until| source.at.until((Unit::Day, until)).ok());
+
+        let written = source.at.clone();
+        let occurrences = std::iter::once(written.clone()).chain(
+            rule.occurrences(&source.at, window.until)
+                .filter(move |at| *at != written),
+        );
+
+        for at in occurrences {
+            let until = length.and_then(|length| at.checked_add(length).ok());
+
+            let occurrence = Record {
+                at,
+                until,
+                recurrence: None,
+                source: Some(source.clone()),
+                ..Record::clone(&source)
+            };
+
+            let before_window = window.from.is_some_and(|from| match occurrence.end() {
+                Some(end) => end.timestamp() <= from,
+                None => occurrence.at.timestamp() < from,
+            });
+            if before_window {
+                continue;
+            }
+
+            expanded.push(occurrence);
+        }
+    }
+
+    expanded
+}
+
+#[cfg(test)]
+mod tests {
+    use jiff::tz::TimeZone;
+    use jiff::Timestamp;
+
+    use super::Frequency;
+    use super::RecurrenceRule;
+    use super::Window;
+    use crate::parsing::parse_definition;
+    use crate::parsing::parse_record;
+    use crate::parsing::parse_timestamp;
+
+    #[test]
+    fn monthly_rules_skip_missing_days() {
+        let rule = RecurrenceRule::parse("FREQ=MONTHLY;COUNT=4", &TimeZone::UTC).unwrap();
+        assert_eq!(rule.frequency, Frequency::Monthly);
+
+        let (start, _) = parse_timestamp("2025-01-31", &TimeZone::UTC).unwrap();
+        let occurrences = rule
+            .occurrences(&start, Timestamp::MAX)
+            .map(|at| at.date().to_string())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            occurrences,
+            ["2025-01-31", "2025-03-31", "2025-05-31", "2025-07-31"]
+        );
+    }
+
+    #[test]
+    fn until_is_inclusive() {
+        let rule =
+            RecurrenceRule::parse("FREQ=WEEKLY;INTERVAL=2;UNTIL=20250115", &TimeZone::UTC).unwrap();
+
+        let (start, _) = parse_timestamp("2025-01-01", &TimeZone::UTC).unwrap();
+        let occurrences = rule.occurrences(&start, Timestamp::MAX).count();
+
+        assert_eq!(occurrences, 2);
+        assert!(RecurrenceRule::parse("FREQ=HOURLY", &TimeZone::UTC).is_err());
+    }
+
+    #[test]
+    fn records_starting_later_keep_their_written_occurrence() {
+        let definitions = [(
+            String::from("rent"),
+            parse_definition(
+                "define since=\"2024-01-01\" {\n\tfields {\n\t}\n}\n",
+                String::from("rent"),
+                &TimeZone::UTC,
+            )
+            .unwrap(),
+        )]
+        .into();
+        let records = parse_record(
+            "rent \"2025-03-01\" repeat=\"FREQ=MONTHLY\"\n",
+            "rent.plrecs".into(),
+            &definitions,
+            &TimeZone::UTC,
+        )
+        .unwrap();
+
+        let until = |until: &str| Window {
+            from: None,
+            until: until.parse().unwrap(),
+        };
+        let dates = |window: &Window| {
+            super::expand(records.clone(), window)
+                .iter()
+                .map(|occurrence| occurrence.at.date().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(dates(&until("2025-01-01T00:00:00Z")), ["2025-03-01"]);
+        assert_eq!(
+            dates(&until("2025-04-15T00:00:00Z")),
+            ["2025-03-01", "2025-04-01"]
+        );
+    }
+}
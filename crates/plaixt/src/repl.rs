@@ -0,0 +1,465 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use async_graphql_parser::types::BaseType;
+use async_graphql_parser::types::TypeKind;
+use async_graphql_parser::types::TypeSystemDefinition;
+use camino::Utf8Path;
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::ValidationContext;
+use rustyline::validate::ValidationResult;
+use rustyline::validate::Validator;
+use rustyline::Editor;
+use rustyline::Helper;
+
+use crate::adapter::Adapter;
+use crate::config::Config;
+use crate::config::OutputFormat;
+use crate::parsing::LoadOptions;
+use crate::query::Query;
+
+const COMMANDS: &[&str] = &[":help", ":output", ":quit", ":reload", ":var"];
+
+const HELP: &str = "\
+Queries may span multiple lines, they are run once all braces are closed.
+
+:reload              Read definitions and records again
+:output FORMAT       Write results as table, json, ndjson or csv
+:var NAME=VALUE      Set a variable for the following queries
+:var                 List the variables that are set
+:quit                Leave, as does Ctrl-D";
+
+/// The repository as queries see it, replaced as a whole on `:reload`
+struct Loaded {
+    schema: trustfall::Schema,
+    adapter: Arc<Adapter>,
+    completion: Completion,
+    records: usize,
+}
+
+async fn load(
+    root_folder: &Utf8Path,
+    config: &Config,
+    options: &LoadOptions,
+) -> miette::Result<Loaded> {
+    let definitions =
+        crate::parsing::load_definitions(&root_folder.join(&config.definitions), &config.timezone)
+            .await?;
+    let (records, schema, adapter) =
+        crate::load_queryable(root_folder, config, &definitions, options).await?;
+
+    Ok(Loaded {
+        schema,
+        adapter: Arc::new(adapter),
+        completion: Completion::new(&crate::adapter::schema_text(&definitions)),
+        records: records.records().len(),
+    })
+}
+
+/// Reads queries from the terminal and writes their results, until the input ends
+///
+/// Definitions and records stay loaded in between queries. If reloading them fails, the
+/// previously loaded ones are kept.
+pub(crate) async fn run(
+    root_folder: &Utf8Path,
+    config: &Config,
+    options: &LoadOptions,
+) -> miette::Result<()> {
+    let mut loaded = load(root_folder, config, options).await?;
+    let mut output = config.output;
+    let mut variables = BTreeMap::<String, String>::new();
+
+    let mut editor = Editor::<ReplHelper, DefaultHistory>::new().map_err(repl_error)?;
+    editor.set_helper(Some(ReplHelper {
+        completion: loaded.completion.clone(),
+    }));
+
+    eprintln!("Loaded {} records, enter a query or :help", loaded.records);
+
+    loop {
+        let input = match editor.readline("plaixt> ") {
+            Ok(input) => input,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(repl_error(e)),
+        };
+
+        let input = input.trim();
+        if input.is_empty() {
+            continue;
+        }
+        let _ = editor.add_history_entry(input);
+
+        let (command, argument) = input.split_once(' ').unwrap_or((input, ""));
+        let argument = argument.trim();
+
+        match command {
+            ":quit" | ":q" => break,
+            ":help" => eprintln!("{HELP}"),
+            ":reload" => match load(root_folder, config, options).await {
+                Ok(reloaded) => {
+                    eprintln!("Loaded {} records", reloaded.records);
+                    if let Some(helper) = editor.helper_mut() {
+                        helper.completion = reloaded.completion.clone();
+                    }
+                    loaded = reloaded;
+                }
+                Err(e) => eprintln!("{e:?}"),
+            },
+            ":output" => match <OutputFormat as clap::ValueEnum>::from_str(argument, true) {
+                Ok(format) => output = format,
+                Err(_) => {
+                    eprintln!("Unknown output \"{argument}\", use table, json, ndjson or csv")
+                }
+            },
+            ":var" if argument.is_empty() => {
+                for (name, value) in &variables {
+                    eprintln!("{name} = {value}");
+                }
+            }
+            ":var" => match crate::add::parse_assignment(argument) {
+                Ok((name, value)) => {
+                    variables.insert(name, value);
+                }
+                Err(e) => eprintln!("{e}"),
+            },
+            command if command.starts_with(':') => {
+                eprintln!("Unknown command \"{command}\", see :help");
+            }
+            _ => {
+                if let Err(e) = run_query(&loaded, input, &variables, output, &config.timezone) {
+                    eprintln!("{e:?}");
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn run_query(
+    loaded: &Loaded,
+    text: &str,
+    variables: &BTreeMap<String, String>,
+    output: OutputFormat,
+    timezone: &jiff::tz::TimeZone,
+) -> miette::Result<()> {
+    let query = Query::parse(&loaded.schema, "query", text, timezone)?;
+
+    // Variables are kept for later queries, only the ones this query declares are used
+    let declared = &query.parsed.ir_query.variables;
+    let missing = declared
+        .keys()
+        .filter(|name| !variables.contains_key(name.as_ref()))
+        .map(|name| format!("${name}"))
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        miette::bail!(
+            help = "Set them with `:var name=value`",
+            "Missing values for {}",
+            missing.join(", ")
+        );
+    }
+
+    let given = variables
+        .iter()
+        .filter(|(name, _)| declared.contains_key(name.as_str()))
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+
+    let variables = query.variables(given, timezone)?;
+    let rows = query.execute(loaded.adapter.clone(), variables)?;
+
+    crate::output::write_rows(
+        output,
+        &query.columns(),
+        rows,
+        &mut std::io::stdout().lock(),
+    )
+    .map_err(|e| miette::miette!("Could not write the results: {e}"))
+}
+
+fn repl_error(error: ReadlineError) -> miette::Report {
+    miette::miette!("Could not read from the terminal: {error}")
+}
+
+/// Names to complete, read from the schema
+#[derive(Debug, Clone, Default)]
+struct Completion {
+    query_type: String,
+    /// The fields of every type, with the type they lead to
+    types: BTreeMap<String, Vec<(String, String)>>,
+    directives: Vec<String>,
+}
+
+impl Completion {
+    fn new(schema: &str) -> Completion {
+        let mut completion = Completion::default();
+        let Ok(document) = async_graphql_parser::parse_schema(schema) else {
+            return completion;
+        };
+
+        for definition in document.definitions {
+            match definition {
+                TypeSystemDefinition::Schema(schema) => {
+                    if let Some(query) = &schema.node.query {
+                        completion.query_type = query.node.to_string();
+                    }
+                }
+                TypeSystemDefinition::Type(ty) => {
+                    let fields = match &ty.node.kind {
+                        TypeKind::Object(object) => &object.fields,
+                        TypeKind::Interface(interface) => &interface.fields,
+                        _ => continue,
+                    };
+
+                    let fields = fields
+                        .iter()
+                        .map(|field| {
+                            let mut ty = &field.node.ty.node;
+                            while let BaseType::List(inner) = &ty.base {
+                                ty = inner;
+                            }
+                            (field.node.name.node.to_string(), ty.base.to_string())
+                        })
+                        .collect();
+
+                    completion
+                        .types
+                        .insert(ty.node.name.node.to_string(), fields);
+                }
+                TypeSystemDefinition::Directive(directive) => {
+                    completion
+                        .directives
+                        .push(format!("@{}", directive.node.name.node));
+                }
+            }
+        }
+
+        completion
+    }
+
+    /// Where the word before the cursor starts, and what it could be completed to
+    fn complete(&self, before: &str) -> (usize, Vec<String>) {
+        let start = before
+            .rfind(|c: char| !(c.is_alphanumeric() || matches!(c, '_' | '@' | ':')))
+            .map_or(0, |idx| idx + 1);
+        let word = &before[start..];
+
+        let candidates: Vec<&str> = if word.starts_with(':') {
+            COMMANDS.to_vec()
+        } else if word.starts_with('@') {
+            self.directives.iter().map(String::as_str).collect()
+        } else if before[..start].trim_end().ends_with(" on") {
+            self.types.keys().map(String::as_str).collect()
+        } else {
+            match self.scope(&before[..start]) {
+                Some(ty) => self
+                    .types
+                    .get(&ty)
+                    .into_iter()
+                    .flatten()
+                    .map(|(field, _)| field.as_str())
+                    .collect(),
+                None => vec![],
+            }
+        };
+
+        let candidates = candidates
+            .into_iter()
+            .filter(|candidate| candidate.starts_with(word))
+            .map(str::to_string)
+            .collect();
+
+        (start, candidates)
+    }
+
+    /// The type whose fields can be written at the end of `text`
+    fn scope(&self, text: &str) -> Option<String> {
+        let mut scopes: Vec<Option<String>> = vec![];
+        let mut last_field: Option<String> = None;
+        let mut coerced_to: Option<String> = None;
+        let mut previous_word = String::new();
+
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '#' => {
+                    chars.by_ref().find(|c| *c == '\n');
+                }
+                '"' => {
+                    let mut escaped = false;
+                    for c in chars.by_ref() {
+                        match c {
+                            '"' if !escaped => break,
+                            '\\' => escaped = !escaped,
+                            _ => escaped = false,
+                        }
+                    }
+                }
+                '(' => {
+                    let mut depth = 1;
+                    for c in chars.by_ref() {
+                        match c {
+                            '(' => depth += 1,
+                            ')' if depth == 1 => break,
+                            ')' => depth -= 1,
+                            _ => {}
+                        }
+                    }
+                }
+                '{' => {
+                    let scope = match (coerced_to.take(), scopes.last()) {
+                        (Some(ty), _) => Some(ty),
+                        (None, None) => Some(self.query_type.clone()),
+                        (None, Some(parent)) => parent.as_ref().and_then(|parent| {
+                            let field = last_field.as_ref()?;
+                            self.types
+                                .get(parent)?
+                                .iter()
+                                .find(|(name, _)| name == field)
+                                .map(|(_, ty)| ty.clone())
+                        }),
+                    };
+                    scopes.push(scope);
+                    last_field = None;
+                }
+                '}' => {
+                    scopes.pop();
+                    last_field = None;
+                }
+                '@' => {
+                    // Directives are not fields
+                    while chars
+                        .next_if(|c| c.is_alphanumeric() || *c == '_')
+                        .is_some()
+                    {}
+                }
+                c if c.is_alphanumeric() || c == '_' => {
+                    let mut word = String::from(c);
+                    while let Some(c) = chars.next_if(|c| c.is_alphanumeric() || *c == '_') {
+                        word.push(c);
+                    }
+
+                    if previous_word == "on" {
+                        coerced_to = Some(word.clone());
+                    } else if word != "on" {
+                        last_field = Some(word.clone());
+                    }
+                    previous_word = word;
+                }
+                _ => {}
+            }
+        }
+
+        scopes.pop().flatten()
+    }
+}
+
+/// Whether `input` is a command or a query with all its braces closed
+fn is_complete(input: &str) -> bool {
+    let input = input.trim_start();
+    if input.starts_with(':') {
+        return true;
+    }
+
+    let mut depth = 0i32;
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut in_comment = false;
+
+    for c in input.chars() {
+        match c {
+            '\n' if in_comment => in_comment = false,
+            _ if in_comment => {}
+            '"' if !escaped => in_string = !in_string,
+            '\\' if in_string => {
+                escaped = !escaped;
+                continue;
+            }
+            '#' if !in_string => in_comment = true,
+            '{' if !in_string => depth += 1,
+            '}' if !in_string => depth -= 1,
+            _ => {}
+        }
+        escaped = false;
+    }
+
+    depth <= 0
+}
+
+struct ReplHelper {
+    completion: Completion,
+}
+
+impl Helper for ReplHelper {}
+
+impl Highlighter for ReplHelper {}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Completer for ReplHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &rustyline::Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(self.completion.complete(&line[..pos]))
+    }
+}
+
+impl Validator for ReplHelper {
+    fn validate(&self, ctx: &mut ValidationContext<'_>) -> rustyline::Result<ValidationResult> {
+        match is_complete(ctx.input()) {
+            true => Ok(ValidationResult::Valid(None)),
+            false => Ok(ValidationResult::Incomplete),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_complete;
+    use super::Completion;
+
+    #[test]
+    fn completion_follows_the_query() {
+        let completion = Completion::new(
+            "schema { query: Root }
+            type Root { Records: [Record!]! }
+            interface Record { _kind: String! _source: [Record!] }
+            type p_changelog implements Record { _kind: String! _source: [Record!] title: String }
+            directive @output(name: String) on FIELD",
+        );
+
+        assert_eq!(completion.complete("{ Rec").1, ["Records"]);
+        assert_eq!(completion.complete("{ Records { _").1, ["_kind", "_source"]);
+        assert_eq!(
+            completion.complete("{ Records { ... on p_").1,
+            ["p_changelog"]
+        );
+        assert_eq!(
+            completion
+                .complete("{ Records { ... on p_changelog { _kind @output(name: \"{\") t")
+                .1,
+            ["title"]
+        );
+        assert_eq!(
+            completion.complete("{ Records { _source { _kind @o").1,
+            ["@output"]
+        );
+
+        assert!(!is_complete("{ Records {\n  _kind @output"));
+        assert!(is_complete("{ Records { # }\n _kind @output } }"));
+        assert!(is_complete(":reload"));
+    }
+}
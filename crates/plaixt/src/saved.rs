@@ -0,0 +1,304 @@
+use std::collections::BTreeSet;
+
+use camino::Utf8Path;
+use camino::Utf8PathBuf;
+use miette::IntoDiagnostic;
+use miette::Severity;
+use miette::WrapErr;
+
+use crate::config::OutputFormat;
+use crate::query::header;
+
+/// A query kept in the repository, which can be run as its own subcommand
+///
+/// Saved queries live in the `queries` folder of a repository, one per `.graphql` or
+/// `.trustfall` file, and are named after their file. Leading `#` comments describe them:
+///
+/// - `# description: ...` is shown in `--help`
+/// - `# param: name` declares a required parameter, given as `--name value`, and
+///   `# param: name = value` one with a default
+/// - `# output: csv` sets the output format, unless `--output` is given
+#[derive(Debug, Clone)]
+pub struct SavedQuery {
+    pub(crate) name: String,
+    pub(crate) path: Utf8PathBuf,
+    pub(crate) query: String,
+    pub(crate) description: Option<String>,
+    pub(crate) parameters: Vec<Parameter>,
+    pub(crate) output: Option<OutputFormat>,
+}
+
+/// A variable of a saved query that is given on the command line
+#[derive(Debug, Clone)]
+pub struct Parameter {
+    pub(crate) name: String,
+    pub(crate) default: Option<String>,
+}
+
+/// The names parameters cannot have, as they are taken by the options of every saved query
+///
+/// These are `--output` and `--help`, as well as the arguments of `command` that saved
+/// queries are added to, since its global ones are also given to them.
+pub(crate) fn reserved_parameters(command: &clap::Command) -> BTreeSet<String> {
+    let mut reserved = BTreeSet::from(["output", "help"].map(String::from));
+    for arg in command.get_arguments() {
+        reserved.insert(arg.get_id().to_string());
+        reserved.extend(arg.get_long().map(str::to_string));
+    }
+
+    reserved
+}
+
+impl SavedQuery {
+    fn parse(
+        path: Utf8PathBuf,
+        query: String,
+        reserved: &BTreeSet<String>,
+    ) -> miette::Result<SavedQuery> {
+        let header = header(&query);
+
+        let description = header
+            .iter()
+            .find_map(|comment| comment.strip_prefix("description:"))
+            .map(|description| description.trim().to_string());
+
+        let parameters = header
+            .iter()
+            .filter_map(|comment| comment.strip_prefix("param:"))
+            .map(|param| match param.split_once('=') {
+                Some((name, default)) => Parameter {
+                    name: name.trim().to_string(),
+                    default: Some(default.trim().to_string()),
+                },
+                None => Parameter {
+                    name: param.trim().to_string(),
+                    default: None,
+                },
+            })
+            .collect::<Vec<_>>();
+
+        if let Some(param) = parameters
+            .iter()
+            .find(|param| reserved.contains(&param.name))
+        {
+            miette::bail!(
+                help = "Rename the variable in the query",
+                "The saved query {path} can not have a parameter called \"{}\"",
+                param.name
+            );
+        }
+
+        let output = header
+            .iter()
+            .find_map(|comment| comment.strip_prefix("output:"))
+            .map(|output| {
+                <OutputFormat as clap::ValueEnum>::from_str(output.trim(), true).map_err(|_| {
+                    miette::miette!(
+                        help = "Use one of table, json, ndjson or csv",
+                        "The saved query {path} has an unknown output \"{}\"",
+                        output.trim()
+                    )
+                })
+            })
+            .transpose()?;
+
+        Ok(SavedQuery {
+            name: path.file_stem().unwrap_or(path.as_str()).to_string(),
+            path,
+            query,
+            description,
+            parameters,
+            output,
+        })
+    }
+
+    /// The subcommand running this query, with one option per parameter
+    pub(crate) fn command(&self) -> clap::Command {
+        let mut command = clap::Command::new(self.name.clone()).arg(
+            clap::Arg::new("output")
+                .short('o')
+                .long("output")
+                .value_name("OUTPUT")
+                .value_parser(clap::value_parser!(OutputFormat))
+                .help("How to write the results, per default the output of the query"),
+        );
+
+        if let Some(description) = &self.description {
+            command = command.about(description.clone());
+        }
+
+        for param in &self.parameters {
+            let mut arg = clap::Arg::new(param.name.clone())
+                .long(param.name.clone())
+                .value_name("VALUE")
+                .help(format!("The value of ${}", param.name));
+
+            arg = match &param.default {
+                Some(default) => arg.default_value(default.clone()),
+                None => arg.required(true),
+            };
+
+            command = command.arg(arg);
+        }
+
+        command
+    }
+
+    /// The variables and output format given to the subcommand of this query
+    pub(crate) fn arguments(
+        &self,
+        matches: &clap::ArgMatches,
+    ) -> (Vec<(String, String)>, Option<OutputFormat>) {
+        let variables = self
+            .parameters
+            .iter()
+            .filter_map(|param| {
+                let value = matches.get_one::<String>(&param.name)?;
+                Some((param.name.clone(), value.clone()))
+            })
+            .collect();
+
+        let output = matches.get_one::<OutputFormat>("output").copied();
+
+        (variables, output.or(self.output))
+    }
+}
+
+/// Loads the saved queries in `path`, ordered by name
+///
+/// A broken saved query should not keep any other command from running, so it is left out
+/// and reported in `warnings` instead. So is one with a parameter named like one of
+/// `reserved`.
+pub(crate) async fn load_saved_queries(
+    path: &Utf8Path,
+    reserved: &BTreeSet<String>,
+    warnings: &mut Vec<miette::Report>,
+) -> Vec<SavedQuery> {
+    let mut queries = vec![];
+
+    if let Err(error) = read_saved_queries(path, reserved, &mut queries, warnings).await {
+        warnings.push(miette::miette!(
+            severity = Severity::Warning,
+            "Could not read the saved queries in {path}: {error}"
+        ));
+    }
+    queries.sort_by(|a, b| a.name.cmp(&b.name));
+
+    queries
+}
+
+async fn read_saved_queries(
+    path: &Utf8Path,
+    reserved: &BTreeSet<String>,
+    queries: &mut Vec<SavedQuery>,
+    warnings: &mut Vec<miette::Report>,
+) -> miette::Result<()> {
+    if !tokio::fs::try_exists(path).await.into_diagnostic()? {
+        return Ok(());
+    }
+
+    let mut entries = tokio::fs::read_dir(path).await.into_diagnostic()?;
+
+    while let Some(entry) = entries.next_entry().await.into_diagnostic()? {
+        let Ok(path) = Utf8PathBuf::from_path_buf(entry.path()) else {
+            continue;
+        };
+
+        let is_hidden = path.file_name().is_some_and(|name| name.starts_with('.'));
+        let is_query = matches!(path.extension(), Some("graphql" | "trustfall"));
+        if is_hidden || !is_query || !entry.file_type().await.into_diagnostic()?.is_file() {
+            continue;
+        }
+
+        let query = tokio::fs::read_to_string(&path)
+            .await
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Could not read the saved query {path}"))
+            .and_then(|query| SavedQuery::parse(path.clone(), query, reserved));
+        match query {
+            Ok(query) => queries.push(query),
+            Err(error) => warnings.push(miette::miette!(
+                severity = Severity::Warning,
+                help = error
+                    .help()
+                    .map(|help| help.to_string())
+                    .unwrap_or_default(),
+                "{error}, so it was skipped"
+            )),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+
+    use super::load_saved_queries;
+    use super::reserved_parameters;
+    use super::SavedQuery;
+    use crate::config::OutputFormat;
+
+    #[tokio::test]
+    async fn broken_saved_queries_are_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = camino::Utf8Path::from_path(dir.path()).unwrap();
+        std::fs::write(dir.join("recent.graphql"), "{}").unwrap();
+        std::fs::write(dir.join("broken.graphql"), "# output: pdf\n{}").unwrap();
+        std::fs::write(dir.join("strict.graphql"), "# param: lenient\n{}").unwrap();
+
+        let reserved = reserved_parameters(&crate::Args::command());
+        let mut warnings = vec![];
+        let saved = load_saved_queries(dir, &reserved, &mut warnings).await;
+
+        assert_eq!(
+            saved
+                .iter()
+                .map(|saved| saved.name.as_str())
+                .collect::<Vec<_>>(),
+            ["recent"]
+        );
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings
+            .iter()
+            .any(|warning| warning.to_string().contains("broken.graphql")));
+        assert!(warnings
+            .iter()
+            .any(|warning| warning.to_string().contains("\"lenient\"")));
+    }
+
+    #[test]
+    fn header_describes_the_command() {
+        let saved = SavedQuery::parse(
+            "queries/out-of-warranty.graphql".into(),
+            String::from(
+                "# description: Purchases whose warranty has run out\n# param: kind\n# param: before = 2025-01-01\n# output: csv\n{}",
+            ),
+            &reserved_parameters(&crate::Args::command()),
+        )
+        .unwrap();
+
+        assert_eq!(saved.name, "out-of-warranty");
+        assert_eq!(saved.output, Some(OutputFormat::Csv));
+
+        let matches = saved
+            .command()
+            .try_get_matches_from(["out-of-warranty", "--kind", "tool"])
+            .unwrap();
+        let (variables, output) = saved.arguments(&matches);
+        assert_eq!(
+            variables,
+            [
+                (String::from("kind"), String::from("tool")),
+                (String::from("before"), String::from("2025-01-01"))
+            ]
+        );
+        assert_eq!(output, Some(OutputFormat::Csv));
+
+        assert!(saved
+            .command()
+            .try_get_matches_from(["out-of-warranty"])
+            .is_err());
+    }
+}
@@ -0,0 +1,288 @@
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::future::Future;
+use std::io::IsTerminal;
+use std::sync::Arc;
+use std::time::Duration;
+
+use camino::Utf8Path;
+use camino::Utf8PathBuf;
+use miette::IntoDiagnostic;
+use notify::EventKind;
+use notify::RecursiveMode;
+use notify::Watcher;
+use tokio::sync::mpsc::UnboundedReceiver;
+
+use crate::adapter::Adapter;
+use crate::adapter::RecordStore;
+use crate::config::Config;
+use crate::parsing::Definition;
+use crate::parsing::LoadOptions;
+use crate::parsing::ParsedFile;
+
+/// How long to wait for further changes before reloading, as editors often save in several steps
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// What a watched command runs on, as of the last change
+pub(crate) struct Loaded {
+    pub(crate) records: Arc<RecordStore>,
+    pub(crate) schema: trustfall::Schema,
+    pub(crate) adapter: Arc<Adapter>,
+}
+
+/// What changed since the last reload
+#[derive(Default)]
+struct Changes {
+    definitions: bool,
+    files: BTreeSet<Utf8PathBuf>,
+}
+
+/// Loads the records and runs `command` on them, and again whenever files below the root
+/// folder change, until interrupted
+///
+/// Only record files that changed are parsed again, a changed definition reloads everything.
+/// Errors are printed instead of stopping, so that they can be fixed while watching.
+pub(crate) async fn watch<F, Fut>(
+    root_folder: &Utf8Path,
+    config: &Config,
+    options: &LoadOptions,
+    mut command: F,
+) -> miette::Result<()>
+where
+    F: FnMut(Loaded) -> Fut,
+    Fut: Future<Output = miette::Result<()>>,
+{
+    let root = root_folder.canonicalize_utf8().into_diagnostic()?;
+    let definitions_folder = root_folder
+        .join(&config.definitions)
+        .canonicalize_utf8()
+        .into_diagnostic()?;
+
+    let (sender, mut events) = tokio::sync::mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |event| {
+        // Only fails once watching stopped
+        let _ = sender.send(event);
+    })
+    .into_diagnostic()?;
+    watcher
+        .watch(root.as_std_path(), RecursiveMode::Recursive)
+        .into_diagnostic()?;
+    if !definitions_folder.starts_with(&root) {
+        watcher
+            .watch(definitions_folder.as_std_path(), RecursiveMode::Recursive)
+            .into_diagnostic()?;
+    }
+
+    let mut definitions = None;
+    let mut files = BTreeMap::new();
+    let mut changes = Changes {
+        definitions: true,
+        files: BTreeSet::new(),
+    };
+
+    loop {
+        if std::io::stdout().is_terminal() {
+            print!("\x1b[2J\x1b[H");
+        }
+
+        let reloaded = reload(
+            root_folder,
+            config,
+            options,
+            &mut definitions,
+            &mut files,
+            changes,
+        )
+        .await;
+        let ran = match reloaded {
+            Ok(loaded) => command(loaded).await,
+            Err(error) => Err(error),
+        };
+        if let Err(error) = ran {
+            eprintln!("{error:?}");
+        }
+
+        eprintln!("Watching for changes, stop with Ctrl-C");
+        changes = match next_changes(&mut events, &root, root_folder, &definitions_folder).await? {
+            Some(changes) => changes,
+            None => return Ok(()),
+        };
+    }
+}
+
+/// Brings `definitions` and `files` up to date with `changes`, and makes them queryable
+///
+/// If loading a file fails, all files are loaded again the next time.
+async fn reload(
+    root_folder: &Utf8Path,
+    config: &Config,
+    options: &LoadOptions,
+    definitions: &mut Option<BTreeMap<String, Vec<Definition>>>,
+    files: &mut BTreeMap<Utf8PathBuf, ParsedFile>,
+    changes: Changes,
+) -> miette::Result<Loaded> {
+    let mut unchanged = std::mem::take(files);
+
+    if changes.definitions || definitions.is_none() {
+        // Records have to be checked against the new definitions, and if they cannot be
+        // loaded there is nothing to check against
+        *definitions = None;
+        unchanged.clear();
+        *definitions = Some(
+            crate::parsing::load_definitions(
+                &root_folder.join(&config.definitions),
+                &config.timezone,
+            )
+            .await?,
+        );
+    }
+    let definitions = definitions.as_ref().expect("definitions were just loaded");
+
+    unchanged.retain(|file, _| !changes.files.contains(file));
+    *files = crate::parsing::load_record_files(
+        root_folder,
+        &config.definitions,
+        &config.records,
+        definitions,
+        &config.timezone,
+        options,
+        unchanged,
+    )
+    .await?;
+
+    let (records, invalid) = crate::parsing::collect_records(files.values().cloned(), options)?;
+
+    crate::warn_invalid(&invalid);
+    let records = RecordStore::new(records, invalid);
+    let (schema, adapter) = crate::get_schema_and_adapter(
+        definitions,
+        records.clone(),
+        config.paperless.clone(),
+        config.timezone.clone(),
+    )?;
+
+    Ok(Loaded {
+        records,
+        schema,
+        adapter: Arc::new(adapter),
+    })
+}
+
+/// Waits for files to change, and then for them to stop changing for a moment
+///
+/// Changed files are named like the record files found below `root_folder`. Hidden files are
+/// left out, like they are when looking for records, except for `.plaixtignore` files.
+async fn next_changes(
+    events: &mut UnboundedReceiver<notify::Result<notify::Event>>,
+    root: &Utf8Path,
+    root_folder: &Utf8Path,
+    definitions_folder: &Utf8Path,
+) -> miette::Result<Option<Changes>> {
+    let mut changes = Changes::default();
+
+    loop {
+        let event = if changes.definitions || !changes.files.is_empty() {
+            match tokio::time::timeout(DEBOUNCE, events.recv()).await {
+                Ok(Some(event)) => event,
+                Ok(None) | Err(_) => return Ok(Some(changes)),
+            }
+        } else {
+            match events.recv().await {
+                Some(event) => event,
+                None => return Ok(None),
+            }
+        };
+
+        let event = event.into_diagnostic()?;
+        if matches!(event.kind, EventKind::Access(_)) {
+            continue;
+        }
+
+        for path in event.paths {
+            let Ok(path) = Utf8PathBuf::from_path_buf(path) else {
+                continue;
+            };
+            if path.starts_with(definitions_folder) {
+                changes.definitions = true;
+                continue;
+            }
+
+            let Ok(relative) = path.strip_prefix(root) else {
+                continue;
+            };
+            let hidden = relative.components().any(|component| {
+                component.as_str().starts_with('.') && component.as_str() != ".plaixtignore"
+            });
+            if !hidden {
+                changes.files.insert(root_folder.join(relative));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use camino::Utf8Path;
+    use notify::event::CreateKind;
+    use notify::event::ModifyKind;
+    use notify::Event;
+    use notify::EventKind;
+
+    use super::next_changes;
+
+    #[tokio::test]
+    async fn changes_are_collected_until_files_settle() {
+        let (sender, mut events) = tokio::sync::mpsc::unbounded_channel();
+        let root = Utf8Path::new("/repo");
+
+        let event = |kind, path: &str| Ok(Event::new(kind).add_path(root.join(path).into()));
+        for (kind, path) in [
+            (EventKind::Create(CreateKind::File), "2025.plrecs"),
+            (EventKind::Modify(ModifyKind::Any), "2025.plrecs"),
+            (
+                EventKind::Modify(ModifyKind::Any),
+                ".plaixt/cache/entry.json",
+            ),
+            (EventKind::Modify(ModifyKind::Any), "notes/.2025.plrecs.swp"),
+            (EventKind::Modify(ModifyKind::Any), "notes/work.plrecs"),
+        ] {
+            sender.send(event(kind, path)).unwrap();
+        }
+
+        let changes = next_changes(&mut events, root, Utf8Path::new("."), &root.join("defs"))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!changes.definitions);
+        assert_eq!(
+            changes
+                .files
+                .iter()
+                .map(|file| file.as_str())
+                .collect::<Vec<_>>(),
+            ["./2025.plrecs", "./notes/work.plrecs"]
+        );
+
+        sender
+            .send(event(
+                EventKind::Modify(ModifyKind::Any),
+                "defs/purchase.pldef",
+            ))
+            .unwrap();
+        drop(sender);
+
+        let changes = next_changes(&mut events, root, Utf8Path::new("."), &root.join("defs"))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(changes.definitions);
+        assert!(changes.files.is_empty());
+
+        assert!(
+            next_changes(&mut events, root, Utf8Path::new("."), &root.join("defs"))
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+}